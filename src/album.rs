@@ -0,0 +1,87 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use teloxide::types::{ChatId, MediaGroupId, Message};
+
+/// 收集的防抖时间：最后一条消息到达后等待这么久，视为这一组消息已接收完整
+const ALBUM_DEBOUNCE: Duration = Duration::from_millis(1500);
+
+/// 正在收集的一组消息
+struct PendingAlbum {
+    messages: Vec<Message>,
+    updated_at: Instant,
+}
+
+/// 按键收集陆续到达的一组消息，例如同一相册的消息或一次转发的多条消息
+pub struct MessageCollector<K> {
+    pending: Mutex<HashMap<K, PendingAlbum>>,
+}
+
+impl<K> Default for MessageCollector<K> {
+    fn default() -> Self {
+        Self {
+            pending: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+/// 按 media_group_id 收集相册消息
+pub type AlbumCollector = MessageCollector<MediaGroupId>;
+pub type SharedAlbums = Arc<AlbumCollector>;
+
+/// 按聊天收集一次转发的多条文字消息
+pub type QuoteCollector = MessageCollector<ChatId>;
+pub type SharedQuotes = Arc<QuoteCollector>;
+
+impl<K: Eq + Hash> MessageCollector<K> {
+    /// 加入一条消息，若是这一组的第一条消息则返回 true
+    ///
+    /// 返回 true 时，调用方负责调用 [`MessageCollector::collect`] 取出这一组消息。
+    pub fn push(&self, group_id: K, msg: Message) -> bool {
+        let mut pending = self.pending.lock().unwrap();
+        match pending.get_mut(&group_id) {
+            Some(album) => {
+                album.messages.push(msg);
+                album.updated_at = Instant::now();
+                false
+            }
+            None => {
+                pending.insert(
+                    group_id,
+                    PendingAlbum {
+                        messages: vec![msg],
+                        updated_at: Instant::now(),
+                    },
+                );
+                true
+            }
+        }
+    }
+
+    /// 这一组消息是否正在收集中
+    pub fn is_collecting(&self, group_id: &K) -> bool {
+        self.pending.lock().unwrap().contains_key(group_id)
+    }
+
+    /// 等待这一组不再有新消息后取出全部消息，按原始顺序排列
+    pub async fn collect(&self, group_id: &K) -> Vec<Message> {
+        loop {
+            let wait = {
+                let mut pending = self.pending.lock().unwrap();
+                let Some(album) = pending.get(group_id) else {
+                    return Vec::new();
+                };
+                let elapsed = album.updated_at.elapsed();
+                if elapsed >= ALBUM_DEBOUNCE {
+                    let mut messages = pending.remove(group_id).unwrap().messages;
+                    messages.sort_by_key(|msg| msg.id.0);
+                    return messages;
+                }
+                ALBUM_DEBOUNCE - elapsed
+            };
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
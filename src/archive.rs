@@ -0,0 +1,335 @@
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use tempfile::NamedTempFile;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::encode::EncoderConfig;
+use crate::i18n::Lang;
+use crate::limits::UserQuota;
+use crate::media::{
+    Converted, Failure, MAX_OUTPUT_SIZE, Target, ZIP_MIME, convert_single, detect_type,
+    error_message, media_dimensions, output_tempfile,
+};
+use crate::options::ProcessOptions;
+use crate::tr;
+
+/// 压缩包中最多处理的文件数
+const MAX_ENTRIES: usize = 200;
+/// 结果压缩包中的清单文件名，转换结果不会使用这个名称
+const MANIFEST_NAME: &str = "manifest.json";
+/// 解压后的总大小上限，防止压缩炸弹
+const MAX_TOTAL_UNCOMPRESSED: u64 = 200 * 1024 * 1024;
+
+/// 清单中的单个条目
+#[derive(Debug, Serialize)]
+struct ManifestEntry {
+    /// 压缩包中的原始路径
+    input: String,
+    /// 结果在输出压缩包中的路径
+    #[serde(skip_serializing_if = "Option::is_none")]
+    output: Option<String>,
+    /// "ok" 或 "error"
+    status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    input_size: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    output_size: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    width: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    height: Option<u32>,
+}
+
+/// 结果压缩包中的清单
+#[derive(Debug, Serialize)]
+struct Manifest {
+    target: String,
+    succeeded: usize,
+    failed: usize,
+    files: Vec<ManifestEntry>,
+}
+
+/// 是否为应当忽略的条目（macOS 元数据、隐藏文件）
+fn is_ignored(path: &Path) -> bool {
+    path.components().any(|component| {
+        let name = component.as_os_str().to_string_lossy();
+        name == "__MACOSX" || name.starts_with('.')
+    })
+}
+
+/// 根据输出文件的扩展名生成结果路径，不与 `used` 中已有的名称重复
+///
+/// 优先替换原扩展名（`a.png` → `a.webp`），重复时保留原扩展名（`a.png.webp`），仍重复时追加序号。
+fn output_name(input: &Path, converted: &Converted, used: &mut HashSet<String>) -> String {
+    let extension = converted
+        .path
+        .extension()
+        .map(|extension| extension.to_string_lossy().into_owned());
+    let with_suffix = |suffix: &str| {
+        let mut name = input.as_os_str().to_owned();
+        name.push(suffix);
+        PathBuf::from(name)
+    };
+    let (replaced, kept) = match &extension {
+        Some(extension) => (
+            input.with_extension(extension),
+            with_suffix(&format!(".{}", extension)),
+        ),
+        None => (input.to_path_buf(), input.to_path_buf()),
+    };
+    let numbered = (2..).map(|index| match &extension {
+        Some(extension) => with_suffix(&format!("_{}.{}", index, extension)),
+        None => with_suffix(&format!("_{}", index)),
+    });
+    [replaced, kept]
+        .into_iter()
+        .chain(numbered)
+        .map(|candidate| candidate.to_string_lossy().into_owned())
+        .find(|name| used.insert(name.clone()))
+        .expect("序号递增，总能找到未使用的名称")
+}
+
+/// 解压压缩包中的每个文件，按处理目标逐个转换，并将结果与清单 manifest.json 重新打包
+///
+/// 每个文件转换前检查 `quota` 的每日转换次数，用完后剩余的文件记为失败。
+pub async fn convert_archive(
+    input_path: &Path,
+    target: Target,
+    options: &ProcessOptions,
+    encoder: &EncoderConfig,
+    lang: Lang,
+    quota: Option<UserQuota<'_>>,
+) -> Result<Converted> {
+    let mut archive = ZipArchive::new(File::open(input_path)?).context("无法读取ZIP压缩包")?;
+
+    // 先解压到临时文件，检查数量和总大小
+    let mut entries: Vec<(PathBuf, NamedTempFile)> = Vec::new();
+    let mut total_size = 0u64;
+    for index in 0..archive.len() {
+        let mut entry = archive.by_index(index)?;
+        if entry.is_dir() {
+            continue;
+        }
+        let Some(name) = entry.enclosed_name() else {
+            log::warn!("跳过不安全的压缩包路径: {}", entry.name());
+            continue;
+        };
+        if is_ignored(&name) {
+            continue;
+        }
+        if entries.len() >= MAX_ENTRIES {
+            return Err(Failure::Localized(tr!(lang, ArchiveTooManyFiles, MAX_ENTRIES)).into());
+        }
+        let too_large = || {
+            Failure::Localized(tr!(
+                lang,
+                ArchiveTooLarge,
+                MAX_TOTAL_UNCOMPRESSED / (1024 * 1024)
+            ))
+        };
+        // 声明的大小可能被篡改，先按声明的大小快速拒绝，再按实际解压的字节数限制
+        if total_size + entry.size() > MAX_TOTAL_UNCOMPRESSED {
+            return Err(too_large().into());
+        }
+        let mut temp = NamedTempFile::new().context("无法创建解压临时文件")?;
+        let remaining = MAX_TOTAL_UNCOMPRESSED - total_size;
+        total_size +=
+            io::copy(&mut (&mut entry).take(remaining + 1), &mut temp).context("无法解压文件")?;
+        if total_size > MAX_TOTAL_UNCOMPRESSED {
+            return Err(too_large().into());
+        }
+        entries.push((name, temp));
+    }
+    if entries.is_empty() {
+        return Err(Failure::Localized(tr!(lang, ArchiveEmpty)).into());
+    }
+    log::info!("解压完成，共 {} 个文件，目标: {:?}", entries.len(), target);
+
+    // 逐个转换
+    let mut results = Vec::with_capacity(entries.len());
+    let mut converted_count = 0;
+    for (name, temp) in entries {
+        let input_size = temp.as_file().metadata().map(|m| m.len()).unwrap_or(0);
+        let result = match quota.map(|quota| quota.check_next(converted_count)) {
+            Some(Err(e)) => Err(Failure::Localized(e.message(lang)).into()),
+            _ => match detect_type(temp.path()) {
+                Ok(detected) if detected.mime == ZIP_MIME => {
+                    Err(Failure::Localized(tr!(lang, ArchiveNested)).into())
+                }
+                Ok(detected) => {
+                    convert_single(temp, &detected, target, options, encoder, lang).await
+                }
+                Err(e) => Err(e),
+            },
+        };
+        if result.is_ok() {
+            converted_count += 1;
+        }
+        results.push((name, input_size, result));
+    }
+
+    // 打包结果
+    let output = output_tempfile(".zip")?;
+    let mut writer = ZipWriter::new(output.reopen()?);
+    let zip_options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    let mut used_names = HashSet::from([MANIFEST_NAME.to_string()]);
+    let mut manifest = Manifest {
+        target: format!("{:?}", target),
+        succeeded: 0,
+        failed: 0,
+        files: Vec::new(),
+    };
+    for (name, input_size, result) in results {
+        let mut entry = ManifestEntry {
+            input: name.to_string_lossy().into_owned(),
+            output: None,
+            status: "error",
+            error: None,
+            input_size,
+            output_size: None,
+            width: None,
+            height: None,
+        };
+        match result {
+            Ok(converted) => {
+                let output_path = output_name(&name, &converted, &mut used_names);
+                let data = fs::read(&converted.path)?;
+                writer.start_file(output_path.as_str(), zip_options)?;
+                writer.write_all(&data)?;
+
+                if let Ok((width, height)) = detect_type(&converted.path)
+                    .and_then(|detected| media_dimensions(&converted.path, &detected))
+                {
+                    entry.width = Some(width);
+                    entry.height = Some(height);
+                }
+                entry.output = Some(output_path);
+                entry.output_size = Some(data.len() as u64);
+                entry.status = "ok";
+                manifest.succeeded += 1;
+            }
+            Err(e) => {
+                log::warn!("压缩包中的 {:?} 处理失败: {:?}", name, e);
+                entry.error = Some(error_message(&e, lang));
+                manifest.failed += 1;
+            }
+        }
+        manifest.files.push(entry);
+    }
+    writer.start_file(MANIFEST_NAME, zip_options)?;
+    writer.write_all(serde_json::to_string_pretty(&manifest)?.as_bytes())?;
+    writer.finish()?;
+
+    let output_size = fs::metadata(output.path())?.len();
+    if output_size > MAX_OUTPUT_SIZE {
+        return Err(Failure::Localized(tr!(
+            lang,
+            ResultArchiveTooLarge,
+            output_size / (1024 * 1024)
+        ))
+        .into());
+    }
+
+    let mut converted = Converted::new(output, false);
+    converted.caption = Some(tr!(lang, ArchiveDone, manifest.succeeded, manifest.failed));
+    converted.conversions = converted_count;
+    Ok(converted)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use image::{ImageFormat, Rgb, RgbImage};
+
+    use super::*;
+    use crate::encode::{EncoderConfig, EncoderMode};
+
+    fn encode(format: ImageFormat) -> Vec<u8> {
+        let mut data = Vec::new();
+        RgbImage::from_pixel(8, 8, Rgb([255, 0, 0]))
+            .write_to(&mut Cursor::new(&mut data), format)
+            .unwrap();
+        data
+    }
+
+    fn zip_of(entries: &[(&str, Vec<u8>)]) -> NamedTempFile {
+        let file = NamedTempFile::new().unwrap();
+        let mut writer = ZipWriter::new(file.reopen().unwrap());
+        for (name, data) in entries {
+            writer
+                .start_file(*name, SimpleFileOptions::default())
+                .unwrap();
+            writer.write_all(data).unwrap();
+        }
+        writer.finish().unwrap();
+        file
+    }
+
+    fn names(converted: &Converted) -> Vec<String> {
+        let archive = ZipArchive::new(File::open(&converted.path).unwrap()).unwrap();
+        let mut names: Vec<String> = archive.file_names().map(str::to_string).collect();
+        names.sort();
+        names
+    }
+
+    async fn convert(input: &NamedTempFile, target: Target) -> Converted {
+        convert_archive(
+            input.path(),
+            target,
+            &ProcessOptions::default(),
+            &EncoderConfig {
+                mode: EncoderMode::Auto,
+                quality: 90.0,
+                method: 4,
+                alpha_quality: 100,
+                png_level: 2,
+            },
+            Lang::default(),
+            None,
+        )
+        .await
+        .unwrap()
+    }
+
+    /// 同名不同扩展名的文件转换后不会重名
+    #[tokio::test]
+    async fn colliding_outputs_get_unique_names() {
+        let input = zip_of(&[
+            ("a.png", encode(ImageFormat::Png)),
+            ("a.jpg", encode(ImageFormat::Jpeg)),
+            ("b.png", encode(ImageFormat::Png)),
+            ("b.webp.jpg", encode(ImageFormat::Jpeg)),
+            ("b.webp", encode(ImageFormat::Png)),
+        ]);
+        let converted = convert(&input, Target::Sticker).await;
+        assert_eq!(converted.conversions, 5);
+        assert_eq!(
+            names(&converted),
+            [
+                "a.jpg.webp",
+                "a.webp",
+                "b.webp",
+                "b.webp.webp",
+                "b.webp_2.webp",
+                "manifest.json"
+            ]
+        );
+    }
+
+    /// 名为 manifest.json 的条目不会覆盖清单
+    #[tokio::test]
+    async fn manifest_name_is_reserved() {
+        let input = zip_of(&[("manifest.json", encode(ImageFormat::Png))]);
+        let converted = convert(&input, Target::Gif).await;
+        assert_eq!(converted.conversions, 1);
+        assert_eq!(names(&converted), ["manifest.json", "manifest.json_2"]);
+    }
+}
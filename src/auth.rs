@@ -0,0 +1,364 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use teloxide::prelude::*;
+use teloxide::types::{ChatId, UserId};
+
+use crate::i18n::Lang;
+use crate::storage::{JsonWriter, load_json, to_json};
+use crate::tr;
+
+const WHITELIST_FILE: &str = "whitelist.json";
+
+/// 群成员检查结果的缓存时间，避免每条消息都调用 getChatMember
+const MEMBERSHIP_CACHE_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// 持久化的访问规则
+///
+/// 判定顺序（先匹配者生效）：
+/// 1. 管理员 → 允许
+/// 2. 用户在黑名单中 → 拒绝
+/// 3. 聊天在黑名单中 → 拒绝
+/// 4. 未启用白名单 → 允许
+/// 5. 用户在白名单中 → 允许
+/// 6. 聊天在白名单中 → 允许
+/// 7. 用户是任一指定群组的成员 → 允许
+/// 8. 其他 → 拒绝
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AccessRules {
+    /// 是否启用白名单（第 5-7 条规则）
+    pub whitelist_enabled: bool,
+    pub allowed_chats: Vec<ChatId>,
+    pub allowed_users: Vec<UserId>,
+    /// 这些群组的成员均被允许使用
+    pub member_of: Vec<ChatId>,
+    pub denied_users: Vec<UserId>,
+    pub denied_chats: Vec<ChatId>,
+}
+
+/// 命令中指定的规则对象：`123`/`chat:123`、`user:123` 或 `member:-100123`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RuleTarget {
+    Chat(ChatId),
+    User(UserId),
+    MemberOf(ChatId),
+}
+
+impl std::str::FromStr for RuleTarget {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        let (kind, id) = s.split_once(':').unwrap_or(("chat", s));
+        let id: i64 = id
+            .trim()
+            .parse()
+            .map_err(|_| anyhow!("无效的 ID: '{}'", id.trim()))?;
+        match kind.trim() {
+            "chat" => Ok(RuleTarget::Chat(ChatId(id))),
+            "user" if id > 0 => Ok(RuleTarget::User(UserId(id as u64))),
+            "member" => Ok(RuleTarget::MemberOf(ChatId(id))),
+            "user" => Err(anyhow!("无效的用户 ID: '{}'", id)),
+            other => Err(anyhow!(
+                "未知的规则类型: '{}'（可选 chat / user / member）",
+                other
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for RuleTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RuleTarget::Chat(id) => write!(f, "聊天 {}", id),
+            RuleTarget::User(id) => write!(f, "用户 {}", id),
+            RuleTarget::MemberOf(id) => write!(f, "群组 {} 的成员", id),
+        }
+    }
+}
+
+impl RuleTarget {
+    /// 提示给用户的规则对象名称
+    pub fn label(&self, lang: Lang) -> String {
+        match self {
+            RuleTarget::Chat(id) => tr!(lang, RuleChat, id),
+            RuleTarget::User(id) => tr!(lang, RuleUser, id),
+            RuleTarget::MemberOf(id) => tr!(lang, RuleMemberOf, id),
+        }
+    }
+}
+
+/// 授权服务：所有分发分支共享同一个实例
+pub struct AuthService {
+    /// 管理员用户 ID，总是被授权，并且可以管理访问规则
+    admins: Vec<UserId>,
+    rules: Mutex<AccessRules>,
+    /// (群组, 用户) → (是否为成员, 检查时间)
+    membership_cache: Mutex<HashMap<(ChatId, UserId), (bool, Instant)>>,
+    writer: JsonWriter,
+}
+
+pub type SharedAuth = Arc<AuthService>;
+
+/// 解析逗号分隔的 ID 列表
+pub fn parse_id_list(ids_str: &str) -> Vec<i64> {
+    ids_str
+        .split(',')
+        .filter_map(|id_str| id_str.trim().parse::<i64>().ok())
+        .collect()
+}
+
+/// 读取 ID 列表环境变量，未设置时返回 None
+fn env_id_list(name: &str) -> Option<Vec<i64>> {
+    let ids_str = std::env::var(name).ok()?;
+    let ids = parse_id_list(&ids_str);
+    if ids.is_empty() {
+        log::warn!("{} 设置为 '{}', 解析后列表为空。", name, ids_str);
+    }
+    Some(ids)
+}
+
+fn to_user_ids(ids: Vec<i64>) -> Vec<UserId> {
+    ids.into_iter()
+        .filter(|id| *id > 0)
+        .map(|id| UserId(id as u64))
+        .collect()
+}
+
+fn to_chat_ids(ids: Vec<i64>) -> Vec<ChatId> {
+    ids.into_iter().map(ChatId).collect()
+}
+
+impl AccessRules {
+    /// 从环境变量构建访问规则
+    ///
+    /// 只要设置了任一 ALLOWED_* 变量，即启用白名单。
+    fn from_env() -> Self {
+        let allowed_chats = env_id_list("ALLOWED_CHAT_IDS");
+        let allowed_users = env_id_list("ALLOWED_USER_IDS");
+        let member_of = env_id_list("ALLOWED_MEMBER_OF_CHAT_IDS");
+        let whitelist_enabled =
+            allowed_chats.is_some() || allowed_users.is_some() || member_of.is_some();
+
+        Self {
+            whitelist_enabled,
+            allowed_chats: to_chat_ids(allowed_chats.unwrap_or_default()),
+            allowed_users: to_user_ids(allowed_users.unwrap_or_default()),
+            member_of: to_chat_ids(member_of.unwrap_or_default()),
+            denied_users: to_user_ids(env_id_list("DENIED_USER_IDS").unwrap_or_default()),
+            denied_chats: to_chat_ids(env_id_list("DENIED_CHAT_IDS").unwrap_or_default()),
+        }
+    }
+}
+
+/// 向列表中添加元素，返回是否发生了变化
+fn insert_unique<T: PartialEq>(list: &mut Vec<T>, value: T) -> bool {
+    if list.contains(&value) {
+        return false;
+    }
+    list.push(value);
+    true
+}
+
+/// 从列表中移除元素，返回是否发生了变化
+fn remove_value<T: PartialEq>(list: &mut Vec<T>, value: &T) -> bool {
+    let before = list.len();
+    list.retain(|item| item != value);
+    list.len() != before
+}
+
+impl AuthService {
+    /// 从环境变量和持久化文件初始化授权服务
+    ///
+    /// 若数据目录中已有规则文件，则以文件为准；否则使用环境变量。
+    pub fn from_env() -> Result<Self> {
+        let admins = to_user_ids(env_id_list("ADMIN_USER_IDS").unwrap_or_default());
+        if admins.is_empty() {
+            log::info!("未设置 ADMIN_USER_IDS。访问规则只能通过环境变量配置。");
+        } else {
+            log::info!("管理员用户 ID: {:?}", admins);
+        }
+
+        let rules = match load_json::<AccessRules>(WHITELIST_FILE)? {
+            Some(rules) => {
+                log::info!("已从文件加载访问规则: {:?}", rules);
+                rules
+            }
+            None => AccessRules::from_env(),
+        };
+        if rules.whitelist_enabled {
+            log::info!(
+                "白名单已启用。允许的聊天: {:?}, 用户: {:?}, 群组成员: {:?}",
+                rules.allowed_chats,
+                rules.allowed_users,
+                rules.member_of
+            );
+        } else {
+            log::info!("未启用白名单。机器人将响应黑名单以外的所有用户。");
+        }
+
+        Ok(Self {
+            admins,
+            rules: Mutex::new(rules),
+            membership_cache: Mutex::new(HashMap::new()),
+            writer: JsonWriter::new(WHITELIST_FILE),
+        })
+    }
+
+    /// 管理员用户 ID
+    pub fn admins(&self) -> &[UserId] {
+        &self.admins
+    }
+
+    /// 判断用户是否为管理员
+    pub fn is_admin(&self, user_id: UserId) -> bool {
+        self.admins.contains(&user_id)
+    }
+
+    /// 按照 [`AccessRules`] 的判定顺序检查用户在某个聊天中是否被授权
+    pub async fn is_authorized(&self, bot: &Bot, user_id: Option<UserId>, chat_id: ChatId) -> bool {
+        if user_id.is_some_and(|id| self.is_admin(id)) {
+            return true;
+        }
+
+        let member_of = {
+            let rules = self.rules.lock().unwrap();
+            if user_id.is_some_and(|id| rules.denied_users.contains(&id))
+                || rules.denied_chats.contains(&chat_id)
+            {
+                return false;
+            }
+            if !rules.whitelist_enabled
+                || user_id.is_some_and(|id| rules.allowed_users.contains(&id))
+                || rules.allowed_chats.contains(&chat_id)
+            {
+                return true;
+            }
+            rules.member_of.clone()
+        };
+
+        let Some(user_id) = user_id else {
+            return false;
+        };
+        for group in member_of {
+            if self.is_member(bot, group, user_id).await {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// 检查消息的发送者和所在聊天是否被授权
+    pub async fn is_message_authorized(&self, bot: &Bot, msg: &Message) -> bool {
+        let user_id = msg.from.as_ref().map(|user| user.id);
+        self.is_authorized(bot, user_id, msg.chat.id).await
+    }
+
+    /// 通过 getChatMember 检查用户是否为群组成员，结果会被缓存
+    async fn is_member(&self, bot: &Bot, group: ChatId, user_id: UserId) -> bool {
+        if let Some((is_member, checked_at)) =
+            self.membership_cache.lock().unwrap().get(&(group, user_id))
+            && checked_at.elapsed() < MEMBERSHIP_CACHE_TTL
+        {
+            return *is_member;
+        }
+
+        let is_member = match bot.get_chat_member(group, user_id).await {
+            Ok(member) => member.is_present(),
+            Err(e) => {
+                log::warn!(
+                    "无法获取用户 {} 在群组 {} 中的成员信息: {}",
+                    user_id,
+                    group,
+                    e
+                );
+                false
+            }
+        };
+        self.membership_cache
+            .lock()
+            .unwrap()
+            .insert((group, user_id), (is_member, Instant::now()));
+        is_member
+    }
+
+    /// 修改访问规则并持久化，返回是否发生了变化
+    ///
+    /// 在副本上修改并先写入文件，写入成功后才替换内存中的规则，保证两者一致。
+    async fn update(&self, f: impl FnOnce(&mut AccessRules) -> bool) -> Result<bool> {
+        self.writer
+            .commit(
+                || {
+                    let mut updated = self.rules.lock().unwrap().clone();
+                    if !f(&mut updated) {
+                        return Ok(None);
+                    }
+                    let content = to_json(&updated)?;
+                    Ok(Some((updated, content)))
+                },
+                |updated| {
+                    *self.rules.lock().unwrap() = updated;
+                    self.membership_cache.lock().unwrap().clear();
+                },
+            )
+            .await
+    }
+
+    /// 将对象加入白名单，返回是否发生了变化
+    ///
+    /// 若白名单尚未启用，会以该对象作为第一个条目启用白名单。
+    pub async fn allow(&self, target: RuleTarget) -> Result<bool> {
+        self.update(|rules| {
+            let enabled_now = !rules.whitelist_enabled;
+            rules.whitelist_enabled = true;
+            let inserted = match target {
+                RuleTarget::Chat(id) => insert_unique(&mut rules.allowed_chats, id),
+                RuleTarget::User(id) => insert_unique(&mut rules.allowed_users, id),
+                RuleTarget::MemberOf(id) => insert_unique(&mut rules.member_of, id),
+            };
+            inserted || enabled_now
+        })
+        .await
+    }
+
+    /// 将对象移出白名单，返回是否发生了变化
+    pub async fn deny(&self, target: RuleTarget) -> Result<bool> {
+        self.update(|rules| match target {
+            RuleTarget::Chat(id) => remove_value(&mut rules.allowed_chats, &id),
+            RuleTarget::User(id) => remove_value(&mut rules.allowed_users, &id),
+            RuleTarget::MemberOf(id) => remove_value(&mut rules.member_of, &id),
+        })
+        .await
+    }
+
+    /// 将用户或聊天加入黑名单，返回是否发生了变化
+    pub async fn block(&self, target: RuleTarget) -> Result<bool> {
+        self.update(|rules| match target {
+            RuleTarget::User(id) => insert_unique(&mut rules.denied_users, id),
+            RuleTarget::Chat(id) | RuleTarget::MemberOf(id) => {
+                insert_unique(&mut rules.denied_chats, id)
+            }
+        })
+        .await
+    }
+
+    /// 将用户或聊天移出黑名单，返回是否发生了变化
+    pub async fn unblock(&self, target: RuleTarget) -> Result<bool> {
+        self.update(|rules| match target {
+            RuleTarget::User(id) => remove_value(&mut rules.denied_users, &id),
+            RuleTarget::Chat(id) | RuleTarget::MemberOf(id) => {
+                remove_value(&mut rules.denied_chats, &id)
+            }
+        })
+        .await
+    }
+
+    /// 当前访问规则的快照
+    pub fn rules(&self) -> AccessRules {
+        self.rules.lock().unwrap().clone()
+    }
+}
@@ -0,0 +1,156 @@
+use std::collections::{HashMap, VecDeque};
+
+use image::{Rgba, RgbaImage};
+
+use crate::options::{BackgroundMode, BackgroundRemoval};
+
+/// 透明度低于此值的像素视为背景
+pub const TRANSPARENT_ALPHA: u8 = 16;
+
+pub fn color_distance(pixel: &Rgba<u8>, color: [u8; 3]) -> f32 {
+    let [r, g, b, _] = pixel.0;
+    let dr = r as f32 - color[0] as f32;
+    let dg = g as f32 - color[1] as f32;
+    let db = b as f32 - color[2] as f32;
+    (dr * dr + dg * dg + db * db).sqrt()
+}
+
+/// 图片边缘的像素坐标
+fn border_pixels(width: u32, height: u32) -> impl Iterator<Item = (u32, u32)> {
+    let horizontal = (0..width).flat_map(move |x| [(x, 0), (x, height - 1)]);
+    let vertical = (1..height.saturating_sub(1)).flat_map(move |y| [(0, y), (width - 1, y)]);
+    horizontal.chain(vertical)
+}
+
+/// 估计背景颜色：边缘不透明像素中最常见的颜色（按 16 级量化统计后取平均）
+pub fn dominant_border_color(img: &RgbaImage) -> Option<[u8; 3]> {
+    let mut buckets: HashMap<[u8; 3], (u32, [u32; 3])> = HashMap::new();
+    for (x, y) in border_pixels(img.width(), img.height()) {
+        let pixel = img.get_pixel(x, y);
+        if pixel[3] < TRANSPARENT_ALPHA {
+            continue;
+        }
+        let key = [pixel[0] >> 4, pixel[1] >> 4, pixel[2] >> 4];
+        let (count, sum) = buckets.entry(key).or_default();
+        *count += 1;
+        for i in 0..3 {
+            sum[i] += pixel[i] as u32;
+        }
+    }
+    buckets
+        .into_values()
+        .max_by_key(|(count, _)| *count)
+        .map(|(count, sum)| sum.map(|s| (s / count) as u8))
+}
+
+/// 计算背景掩码：true 表示该像素属于背景
+fn background_mask(img: &RgbaImage, removal: &BackgroundRemoval) -> Vec<bool> {
+    let (width, height) = img.dimensions();
+    let tolerance = removal.tolerance as f32;
+    let index = |x: u32, y: u32| (y * width + x) as usize;
+    let mut mask = vec![false; (width * height) as usize];
+
+    match removal.mode {
+        BackgroundMode::Color(color) => {
+            for (x, y, pixel) in img.enumerate_pixels() {
+                mask[index(x, y)] =
+                    pixel[3] < TRANSPARENT_ALPHA || color_distance(pixel, color) <= tolerance;
+            }
+        }
+        BackgroundMode::Auto => {
+            let color = dominant_border_color(img);
+            let is_background = |pixel: &Rgba<u8>| {
+                pixel[3] < TRANSPARENT_ALPHA
+                    || color.is_some_and(|color| color_distance(pixel, color) <= tolerance)
+            };
+            // 从所有边缘像素开始做 4 邻域填充
+            let mut queue = VecDeque::new();
+            for (x, y) in border_pixels(width, height) {
+                if !mask[index(x, y)] && is_background(img.get_pixel(x, y)) {
+                    mask[index(x, y)] = true;
+                    queue.push_back((x, y));
+                }
+            }
+            while let Some((x, y)) = queue.pop_front() {
+                let neighbors = [
+                    (x.wrapping_sub(1), y),
+                    (x + 1, y),
+                    (x, y.wrapping_sub(1)),
+                    (x, y + 1),
+                ];
+                for (nx, ny) in neighbors {
+                    if nx < width
+                        && ny < height
+                        && !mask[index(nx, ny)]
+                        && is_background(img.get_pixel(nx, ny))
+                    {
+                        mask[index(nx, ny)] = true;
+                        queue.push_back((nx, ny));
+                    }
+                }
+            }
+        }
+    }
+    mask
+}
+
+/// 每个前景像素到最近背景像素的距离（8 邻域步数），超过 `limit` 的记为 u8::MAX
+fn edge_distances(mask: &[bool], width: u32, height: u32, limit: u8) -> Vec<u8> {
+    let mut distances: Vec<u8> = mask
+        .iter()
+        .map(|&background| if background { 0 } else { u8::MAX })
+        .collect();
+    let neighbors = |x: u32, y: u32| {
+        (-1i64..=1)
+            .flat_map(move |dy| (-1i64..=1).map(move |dx| (x as i64 + dx, y as i64 + dy)))
+            .filter(move |&(nx, ny)| nx >= 0 && ny >= 0 && nx < width as i64 && ny < height as i64)
+            .map(move |(nx, ny)| (nx as u32, ny as u32))
+    };
+    // 从背景像素开始按层扩展到前景
+    let mut queue: VecDeque<(u32, u32)> = (0..height)
+        .flat_map(|y| (0..width).map(move |x| (x, y)))
+        .filter(|&(x, y)| mask[(y * width + x) as usize])
+        .collect();
+    while let Some((x, y)) = queue.pop_front() {
+        let distance = distances[(y * width + x) as usize];
+        if distance >= limit {
+            continue;
+        }
+        for (nx, ny) in neighbors(x, y) {
+            let i = (ny * width + nx) as usize;
+            if distances[i] == u8::MAX {
+                distances[i] = distance + 1;
+                queue.push_back((nx, ny));
+            }
+        }
+    }
+    distances
+}
+
+/// 去除图片背景，背景像素变为透明，前景边缘按羽化距离逐渐透明
+///
+/// 没有检测到背景或整张图片都被判定为背景时保持原样，返回 false。
+pub fn remove_background(img: &mut RgbaImage, removal: &BackgroundRemoval) -> bool {
+    let (width, height) = img.dimensions();
+    if width == 0 || height == 0 {
+        return false;
+    }
+    let mask = background_mask(img, removal);
+    let background_count = mask.iter().filter(|&&background| background).count();
+    if background_count == 0 || background_count == mask.len() {
+        log::debug!("未检测到可去除的背景");
+        return false;
+    }
+
+    let distances = edge_distances(&mask, width, height, removal.feather);
+    for (x, y, pixel) in img.enumerate_pixels_mut() {
+        let distance = distances[(y * width + x) as usize];
+        if distance == 0 {
+            pixel[3] = 0;
+        } else if distance <= removal.feather {
+            let factor = distance as f32 / (removal.feather as f32 + 1.0);
+            pixel[3] = (pixel[3] as f32 * factor).round() as u8;
+        }
+    }
+    true
+}
@@ -0,0 +1,149 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::config::env_or;
+use crate::storage::{JsonWriter, load_json, to_json};
+
+const CACHE_FILE: &str = "cache.json";
+
+/// 结果缓存配置
+#[derive(Clone, Copy, Debug)]
+pub struct CacheConfig {
+    /// 缓存有效期（秒），0 表示禁用缓存
+    pub ttl_secs: u64,
+    /// 最多缓存的结果数，超出后淘汰最旧的结果
+    pub max_entries: usize,
+}
+
+impl CacheConfig {
+    /// 从环境变量读取配置：CACHE_TTL_HOURS（默认 168，即 7 天）、CACHE_MAX_ENTRIES（默认 5000）
+    pub fn from_env() -> Self {
+        Self {
+            ttl_secs: env_or::<u64>("CACHE_TTL_HOURS", 168) * 3600,
+            max_entries: env_or("CACHE_MAX_ENTRIES", 5000),
+        }
+    }
+}
+
+/// 已发送过的处理结果
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CachedResult {
+    /// 发送结果的 file_id，可直接再次发送
+    pub file_id: String,
+    pub is_sticker: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub caption: Option<String>,
+    /// 缓存时间（Unix 时间戳，秒）
+    pub cached_at: u64,
+}
+
+/// 稳定的 FNV-1a 哈希，跨重启保持一致，用于生成缓存键
+pub fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+/// 按 (file_unique_id, 处理目标, 选项哈希, 编码配置哈希) 缓存已发送结果的 file_id，持久化到数据目录
+pub struct ResultCache {
+    config: CacheConfig,
+    entries: Mutex<HashMap<String, CachedResult>>,
+    writer: JsonWriter,
+}
+
+pub type SharedCache = Arc<ResultCache>;
+
+impl ResultCache {
+    /// 从数据目录加载缓存，丢弃已过期的结果
+    pub fn load(config: CacheConfig) -> Result<Self> {
+        let mut entries: HashMap<String, CachedResult> = load_json(CACHE_FILE)?.unwrap_or_default();
+        let now = now_secs();
+        entries.retain(|_, entry| now.saturating_sub(entry.cached_at) < config.ttl_secs);
+        log::info!("已加载 {} 条结果缓存", entries.len());
+        Ok(Self {
+            config,
+            entries: Mutex::new(entries),
+            writer: JsonWriter::new(CACHE_FILE),
+        })
+    }
+
+    fn enabled(&self) -> bool {
+        self.config.ttl_secs > 0 && self.config.max_entries > 0
+    }
+
+    /// 查找未过期的缓存结果
+    pub fn get(&self, key: &str) -> Option<CachedResult> {
+        if !self.enabled() {
+            return None;
+        }
+        let entries = self.entries.lock().unwrap();
+        entries
+            .get(key)
+            .filter(|entry| now_secs().saturating_sub(entry.cached_at) < self.config.ttl_secs)
+            .cloned()
+    }
+
+    /// 缓存一次发送的结果
+    pub async fn insert(
+        &self,
+        key: String,
+        file_id: String,
+        is_sticker: bool,
+        caption: Option<String>,
+    ) -> Result<()> {
+        if !self.enabled() {
+            return Ok(());
+        }
+        {
+            let mut entries = self.entries.lock().unwrap();
+            let now = now_secs();
+            entries.retain(|_, entry| now.saturating_sub(entry.cached_at) < self.config.ttl_secs);
+            entries.insert(
+                key,
+                CachedResult {
+                    file_id,
+                    is_sticker,
+                    caption,
+                    cached_at: now,
+                },
+            );
+            while entries.len() > self.config.max_entries {
+                let Some(oldest) = entries
+                    .iter()
+                    .min_by_key(|(_, entry)| entry.cached_at)
+                    .map(|(key, _)| key.clone())
+                else {
+                    break;
+                };
+                entries.remove(&oldest);
+            }
+        }
+        self.save().await
+    }
+
+    /// 移除失效的缓存结果（例如 file_id 无法再发送）
+    pub async fn remove(&self, key: &str) -> Result<()> {
+        let removed = self.entries.lock().unwrap().remove(key).is_some();
+        if removed {
+            self.save().await?;
+        }
+        Ok(())
+    }
+
+    async fn save(&self) -> Result<()> {
+        self.writer
+            .save(|| to_json(&*self.entries.lock().unwrap()))
+            .await
+    }
+}
@@ -0,0 +1,9 @@
+use std::str::FromStr;
+
+/// 读取并解析环境变量，未设置或无法解析时使用默认值
+pub fn env_or<T: FromStr>(name: &str, default: T) -> T {
+    std::env::var(name)
+        .ok()
+        .and_then(|value| value.trim().parse().ok())
+        .unwrap_or(default)
+}
@@ -0,0 +1,317 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::process::{Command, Stdio};
+#[cfg(feature = "svg")]
+use std::sync::{Arc, OnceLock};
+
+use anyhow::{Context, Result, anyhow};
+use image::metadata::Orientation;
+use image::{DynamicImage, GenericImageView, ImageDecoder, ImageReader, RgbImage, RgbaImage};
+use moxcms::{ColorProfile, DataColorSpace, Layout, TransformOptions};
+#[cfg(feature = "svg")]
+use resvg::usvg::fontdb;
+
+use crate::media::Failure;
+#[cfg(feature = "svg")]
+use crate::text::font_path;
+
+/// SVG 的 MIME 类型
+pub const SVG_MIME: &str = "image/svg+xml";
+/// SVG 栅格化后的长边像素数，为缩放到 512 像素之前的裁剪留出余量
+#[cfg(feature = "svg")]
+const SVG_SIZE: u32 = 1024;
+
+/// 判断文件是否为 SVG：开头为 XML 声明、注释或 `<svg` 标签，且含有 `<svg`
+pub fn is_svg(path: &Path) -> Result<bool> {
+    let mut head = Vec::new();
+    File::open(path)?.take(4096).read_to_end(&mut head)?;
+    let head = String::from_utf8_lossy(&head);
+    let head = head.trim_start_matches('\u{feff}').trim_start();
+    Ok(["<?xml", "<!--", "<!DOCTYPE", "<svg"]
+        .iter()
+        .any(|prefix| head.starts_with(prefix))
+        && head.contains("<svg"))
+}
+
+/// 是否需要交给 FFmpeg 解码（image crate 没有可用的解码器）
+fn needs_ffmpeg(path: &Path) -> Result<bool> {
+    let mime = infer::get_from_path(path)?.map(|kind| kind.mime_type());
+    Ok((cfg!(feature = "avif") && mime == Some("image/avif"))
+        || (cfg!(feature = "heic") && mime == Some("image/heif")))
+}
+
+/// 解码图片：按 EXIF 方向摆正，并把嵌入的 ICC 色彩配置转换为 sRGB
+///
+/// SVG 按 [`SVG_SIZE`] 栅格化，AVIF 和 HEIC 交给 FFmpeg 解码，各自需要开启对应的 feature。
+/// 元数据读取失败时不做处理，只记录警告。
+pub fn load_image(path: &Path) -> Result<DynamicImage> {
+    read_image(path).context(Failure::ImageDecode)
+}
+
+fn read_image(path: &Path) -> Result<DynamicImage> {
+    #[cfg(feature = "svg")]
+    if is_svg(path)? {
+        return rasterize_svg(path);
+    }
+    if needs_ffmpeg(path)? {
+        let dir = tempfile::tempdir()?;
+        let frame_path = dir.path().join("frame.png");
+        decode_with_ffmpeg(path, &frame_path)?;
+        return decode(&frame_path);
+    }
+    decode(path)
+}
+
+/// 使用 image crate 解码
+fn decode(path: &Path) -> Result<DynamicImage> {
+    let mut decoder = ImageReader::open(path)?
+        .with_guessed_format()?
+        .into_decoder()?;
+    let orientation = decoder.orientation().unwrap_or_else(|e| {
+        log::warn!("读取图片方向失败，不旋转: {:?}", e);
+        Orientation::NoTransforms
+    });
+    let icc_profile = decoder.icc_profile().unwrap_or_else(|e| {
+        log::warn!("读取 ICC 色彩配置失败，按 sRGB 处理: {:?}", e);
+        None
+    });
+
+    let mut img = DynamicImage::from_decoder(decoder)?;
+    if let Some(icc_profile) = icc_profile {
+        img = match convert_to_srgb(&img, &icc_profile) {
+            Ok(Some(converted)) => converted,
+            Ok(None) => img,
+            Err(e) => {
+                log::warn!("ICC 色彩配置转换失败，按 sRGB 处理: {:?}", e);
+                img
+            }
+        };
+    }
+    img.apply_orientation(orientation);
+    Ok(img)
+}
+
+/// 读取图片按 EXIF 方向摆正后的尺寸
+///
+/// image crate 能直接解码的格式只读取文件头，其他格式需要完整解码。
+pub fn image_dimensions(path: &Path) -> Result<(u32, u32)> {
+    if (cfg!(feature = "svg") && is_svg(path)?) || needs_ffmpeg(path)? {
+        return Ok(load_image(path)?.dimensions());
+    }
+    let mut decoder = ImageReader::open(path)?
+        .with_guessed_format()?
+        .into_decoder()?;
+    let (width, height) = decoder.dimensions();
+    Ok(match decoder.orientation() {
+        Ok(
+            Orientation::Rotate90
+            | Orientation::Rotate270
+            | Orientation::Rotate90FlipH
+            | Orientation::Rotate270FlipH,
+        ) => (height, width),
+        _ => (width, height),
+    })
+}
+
+/// 使用 FFmpeg 把图片的第一帧解码为 PNG，FFmpeg 会按图片中记录的方向自动旋转
+fn decode_with_ffmpeg(input_path: &Path, output_path: &Path) -> Result<()> {
+    let status = Command::new("ffmpeg")
+        .args([
+            "-y",
+            "-i",
+            input_path.to_str().unwrap(),
+            "-frames:v",
+            "1",
+            output_path.to_str().unwrap(),
+        ])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()?;
+    if !status.success() {
+        return Err(anyhow!("FFmpeg解码图片失败"));
+    }
+    Ok(())
+}
+
+/// SVG 文字使用的字体库，只包含叠加文字使用的字体，首次使用时加载
+#[cfg(feature = "svg")]
+fn svg_fonts() -> Arc<fontdb::Database> {
+    static FONTS: OnceLock<Arc<fontdb::Database>> = OnceLock::new();
+    FONTS
+        .get_or_init(|| {
+            let mut fontdb = fontdb::Database::new();
+            match font_path() {
+                Ok(path) => {
+                    if let Err(e) = fontdb.load_font_file(&path) {
+                        log::warn!("SVG 字体加载失败，文字不绘制: {:?}", e);
+                    }
+                }
+                Err(e) => log::warn!("SVG 字体不可用，文字不绘制: {:?}", e),
+            }
+            // 未指定或找不到的字体族都回退到这个字体
+            let family = fontdb
+                .faces()
+                .next()
+                .and_then(|face| face.families.first())
+                .map(|(family, _)| family.clone());
+            if let Some(family) = family {
+                fontdb.set_serif_family(family.as_str());
+                fontdb.set_sans_serif_family(family.as_str());
+                fontdb.set_monospace_family(family.as_str());
+            }
+            Arc::new(fontdb)
+        })
+        .clone()
+}
+
+/// 把 SVG 栅格化为长边 [`SVG_SIZE`] 像素的图片，文字使用叠加文字的字体
+///
+/// SVG 来自用户，引用的外部图片一律忽略，只接受 `data:` URL 内嵌的图片，防止读取本地文件。
+#[cfg(feature = "svg")]
+fn rasterize_svg(path: &Path) -> Result<DynamicImage> {
+    use resvg::{tiny_skia, usvg};
+
+    let mut options = usvg::Options {
+        fontdb: svg_fonts(),
+        ..Default::default()
+    };
+    options.image_href_resolver = usvg::ImageHrefResolver {
+        resolve_data: usvg::ImageHrefResolver::default_data_resolver(),
+        resolve_string: Box::new(|href, _| {
+            log::warn!("忽略 SVG 引用的外部图片: {}", href);
+            None
+        }),
+    };
+    let tree = usvg::Tree::from_data(&std::fs::read(path)?, &options)?;
+
+    let size = tree.size();
+    let scale = SVG_SIZE as f32 / size.width().max(size.height());
+    let width = ((size.width() * scale).round() as u32).max(1);
+    let height = ((size.height() * scale).round() as u32).max(1);
+    let mut pixmap = tiny_skia::Pixmap::new(width, height).ok_or_else(|| anyhow!("SVG尺寸无效"))?;
+    resvg::render(
+        &tree,
+        tiny_skia::Transform::from_scale(scale, scale),
+        &mut pixmap.as_mut(),
+    );
+
+    // tiny-skia 的像素为预乘透明度的 RGBA
+    let pixels = pixmap
+        .pixels()
+        .iter()
+        .flat_map(|pixel| {
+            let color = pixel.demultiply();
+            [color.red(), color.green(), color.blue(), color.alpha()]
+        })
+        .collect();
+    Ok(DynamicImage::ImageRgba8(
+        RgbaImage::from_raw(width, height, pixels).expect("缓冲区大小与图片一致"),
+    ))
+}
+
+/// 按 ICC 色彩配置把 RGB 图片转换为 sRGB，结果为 8 位
+///
+/// 灰度和 CMYK 等非 RGB 的配置不处理，返回 None。
+fn convert_to_srgb(img: &DynamicImage, icc_profile: &[u8]) -> Result<Option<DynamicImage>> {
+    let source = ColorProfile::new_from_slice(icc_profile)?;
+    if source.color_space != DataColorSpace::Rgb {
+        return Ok(None);
+    }
+    let srgb = ColorProfile::new_srgb();
+    let (width, height) = (img.width(), img.height());
+
+    let converted = if img.color().has_alpha() {
+        let input = img.to_rgba8();
+        let mut output = vec![0u8; input.len()];
+        source
+            .create_transform_8bit(
+                Layout::Rgba,
+                &srgb,
+                Layout::Rgba,
+                TransformOptions::default(),
+            )?
+            .transform(&input, &mut output)?;
+        DynamicImage::ImageRgba8(
+            RgbaImage::from_raw(width, height, output).expect("缓冲区大小与图片一致"),
+        )
+    } else {
+        let input = img.to_rgb8();
+        let mut output = vec![0u8; input.len()];
+        source
+            .create_transform_8bit(Layout::Rgb, &srgb, Layout::Rgb, TransformOptions::default())?
+            .transform(&input, &mut output)?;
+        DynamicImage::ImageRgb8(
+            RgbImage::from_raw(width, height, output).expect("缓冲区大小与图片一致"),
+        )
+    };
+    Ok(Some(converted))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use image::Rgb;
+
+    use super::*;
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(name)
+    }
+
+    /// 40x20 的 JPEG，左半红右半蓝，EXIF 方向为 6（顺时针旋转 90 度）
+    #[test]
+    fn load_image_applies_exif_orientation() {
+        let img = load_image(&fixture("orientation6.jpg")).unwrap();
+        assert_eq!(img.dimensions(), (20, 40));
+        // 摆正后原来的左半边在上方
+        let img = img.to_rgb8();
+        assert!(img.get_pixel(10, 5)[0] > 200);
+        assert!(img.get_pixel(10, 35)[2] > 200);
+    }
+
+    #[test]
+    fn image_dimensions_swaps_for_exif_orientation() {
+        assert_eq!(
+            image_dimensions(&fixture("orientation6.jpg")).unwrap(),
+            (20, 40)
+        );
+    }
+
+    /// 标记为 Display P3 的 (200, 100, 50)，在 sRGB 中约为 (215, 93, 31)
+    #[test]
+    fn load_image_converts_display_p3_to_srgb() {
+        let img = load_image(&fixture("display_p3.png")).unwrap().to_rgb8();
+        let Rgb(pixel) = *img.get_pixel(0, 0);
+        for (actual, expected) in pixel.into_iter().zip([215u8, 93, 31]) {
+            assert!(actual.abs_diff(expected) <= 2, "{:?}", pixel);
+        }
+    }
+
+    /// 引用了本地文件的 SVG：外部图片全部忽略，只绘制 SVG 自身的图形
+    #[cfg(feature = "svg")]
+    #[test]
+    fn rasterize_svg_ignores_external_images() {
+        let img = load_image(&fixture("external_href.svg"))
+            .unwrap()
+            .to_rgba8();
+        assert_eq!(img.dimensions(), (SVG_SIZE, SVG_SIZE));
+        assert_eq!(img.get_pixel(10, 10).0, [255, 0, 0, 255]);
+        assert_eq!(img.get_pixel(512, 256)[3], 0);
+        assert_eq!(img.get_pixel(512, 768)[3], 0);
+    }
+
+    #[test]
+    fn convert_to_srgb_skips_gray_profile() {
+        let mut decoder = ImageReader::open(fixture("gray_icc.png"))
+            .unwrap()
+            .into_decoder()
+            .unwrap();
+        let icc_profile = decoder.icc_profile().unwrap().expect("应嵌入 ICC 色彩配置");
+        let img = DynamicImage::from_decoder(decoder).unwrap();
+        assert!(convert_to_srgb(&img, &icc_profile).unwrap().is_none());
+    }
+}
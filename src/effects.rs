@@ -0,0 +1,148 @@
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, GenericImageView, Rgba, RgbaImage};
+
+use crate::options::{Outline, Shadow};
+
+/// 视为主体的最低透明度
+const SUBJECT_ALPHA: u8 = 128;
+
+/// 代表“无穷远”的平方距离，用有限值避免计算中出现 NaN
+const FAR: f32 = 1e20;
+
+/// 一维平方距离变换（Felzenszwalb & Huttenlocher 的下包络算法）
+fn distance_transform_1d(f: &[f32], output: &mut [f32]) {
+    let n = f.len();
+    let mut v = vec![0usize; n];
+    let mut z = vec![0f32; n + 1];
+    let mut k = 0;
+    z[0] = f32::NEG_INFINITY;
+    z[1] = f32::INFINITY;
+    let intersection = |q: usize, p: usize| {
+        ((f[q] + (q * q) as f32) - (f[p] + (p * p) as f32)) / (2.0 * (q - p) as f32)
+    };
+    for q in 1..n {
+        let mut s = intersection(q, v[k]);
+        while s <= z[k] {
+            k -= 1;
+            s = intersection(q, v[k]);
+        }
+        k += 1;
+        v[k] = q;
+        z[k] = s;
+        z[k + 1] = f32::INFINITY;
+    }
+    k = 0;
+    for (q, out) in output.iter_mut().enumerate() {
+        while z[k + 1] < q as f32 {
+            k += 1;
+        }
+        let d = q as f32 - v[k] as f32;
+        *out = d * d + f[v[k]];
+    }
+}
+
+/// 每个像素到最近主体像素的欧氏距离
+fn subject_distances(img: &RgbaImage) -> Vec<f32> {
+    let (width, height) = (img.width() as usize, img.height() as usize);
+    let mut grid: Vec<f32> = img
+        .pixels()
+        .map(|p| if p[3] >= SUBJECT_ALPHA { 0.0 } else { FAR })
+        .collect();
+
+    let mut column = vec![0f32; height];
+    let mut result = vec![0f32; height];
+    for x in 0..width {
+        for y in 0..height {
+            column[y] = grid[y * width + x];
+        }
+        distance_transform_1d(&column, &mut result);
+        for y in 0..height {
+            grid[y * width + x] = result[y];
+        }
+    }
+    let mut row = vec![0f32; width];
+    for y in 0..height {
+        row.copy_from_slice(&grid[y * width..(y + 1) * width]);
+        distance_transform_1d(&row, &mut grid[y * width..(y + 1) * width]);
+    }
+    grid.into_iter().map(f32::sqrt).collect()
+}
+
+/// 在主体周围描边，描边宽度为 `width` 像素，边缘抗锯齿
+fn outline_layer(img: &RgbaImage, width: u32, color: [u8; 3]) -> RgbaImage {
+    let distances = subject_distances(img);
+    let mut layer = RgbaImage::new(img.width(), img.height());
+    for (pixel, distance) in layer.pixels_mut().zip(distances) {
+        let coverage = (width as f32 + 0.5 - distance).clamp(0.0, 1.0);
+        if coverage > 0.0 {
+            *pixel = Rgba([color[0], color[1], color[2], (coverage * 255.0) as u8]);
+        }
+    }
+    layer
+}
+
+/// 由主体轮廓生成模糊、偏移后的阴影
+fn shadow_layer(img: &RgbaImage, shadow: &Shadow, scale: f32) -> RgbaImage {
+    let offset = (shadow.offset as f32 * scale).round() as i64;
+    let mut silhouette = RgbaImage::new(img.width(), img.height());
+    for (x, y, pixel) in img.enumerate_pixels() {
+        let alpha = (pixel[3] as u32 * shadow.opacity as u32 / 255) as u8;
+        silhouette.put_pixel(x, y, Rgba([0, 0, 0, alpha]));
+    }
+    let mut layer = RgbaImage::new(img.width(), img.height());
+    imageops::overlay(&mut layer, &silhouette, offset, offset);
+    let sigma = shadow.blur as f32 * scale;
+    if sigma > 0.0 {
+        layer = imageops::blur(&layer, sigma);
+    }
+    layer
+}
+
+/// 描边和阴影需要的留白（以 512 像素的贴纸为准）
+fn effect_margin(outline: Option<&Outline>, shadow: Option<&Shadow>) -> u32 {
+    let outline = outline.map_or(0, |outline| outline.width);
+    let shadow = shadow.map_or(0, |shadow| shadow.offset + shadow.blur * 2);
+    outline + shadow
+}
+
+/// 添加描边和阴影：先把主体缩放到 `max_side` 减去留白的大小，再在放大的画布上绘制效果，
+/// 保证效果不会在缩放到 `max_side` 时被裁掉
+pub fn apply_effects(
+    img: DynamicImage,
+    outline: Option<&Outline>,
+    shadow: Option<&Shadow>,
+    max_side: u32,
+) -> DynamicImage {
+    if outline.is_none() && shadow.is_none() {
+        return img;
+    }
+    let scale = max_side as f32 / 512.0;
+    let margin = ((effect_margin(outline, shadow) as f32 * scale).round() as u32).min(max_side / 4);
+    let inner = max_side - 2 * margin;
+    let (width, height) = img.dimensions();
+    let ratio = inner as f32 / width.max(height) as f32;
+    let subject = img
+        .resize_exact(
+            ((width as f32 * ratio).round() as u32).max(1),
+            ((height as f32 * ratio).round() as u32).max(1),
+            FilterType::Lanczos3,
+        )
+        .to_rgba8();
+
+    let mut body = RgbaImage::new(subject.width() + 2 * margin, subject.height() + 2 * margin);
+    imageops::overlay(&mut body, &subject, margin as i64, margin as i64);
+
+    // 描边画在主体下方，阴影由带描边的整体投射
+    if let Some(outline) = outline {
+        let width = ((outline.width as f32 * scale).round() as u32).max(1);
+        let mut outlined = outline_layer(&body, width, outline.color);
+        imageops::overlay(&mut outlined, &body, 0, 0);
+        body = outlined;
+    }
+    let Some(shadow) = shadow else {
+        return DynamicImage::ImageRgba8(body);
+    };
+    let mut result = shadow_layer(&body, shadow, scale);
+    imageops::overlay(&mut result, &body, 0, 0);
+    DynamicImage::ImageRgba8(result)
+}
@@ -0,0 +1,318 @@
+use std::fmt;
+use std::fs;
+use std::io::Cursor;
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::{Result, anyhow};
+use image::{DynamicImage, ImageFormat};
+use webp::WebPConfig;
+
+use crate::cache::fnv1a;
+use crate::config::env_or;
+use crate::i18n::Lang;
+use crate::tr;
+
+/// 有损 WebP 超出大小限制时，每次降低的质量
+const QUALITY_STEP: f32 = 10.0;
+/// 有损 WebP 降低质量的下限
+const MIN_QUALITY: f32 = 30.0;
+
+/// 处理结果即使压缩后仍超出大小限制，`size` 和 `limit` 的单位为字节
+#[derive(Debug)]
+pub enum TooLarge {
+    Image { size: u64, limit: u64 },
+    Video { size: u64, limit: u64 },
+    Gif { size: u64, limit: u64 },
+}
+
+impl TooLarge {
+    /// 提示给用户的消息
+    pub fn message(&self, lang: Lang) -> String {
+        const MB: u64 = 1024 * 1024;
+        match *self {
+            TooLarge::Image { size, limit } => tr!(lang, ImageTooLarge, size / 1024, limit / 1024),
+            TooLarge::Video { size, limit } => tr!(lang, VideoTooLarge, size / 1024, limit / 1024),
+            TooLarge::Gif { size, limit } => tr!(lang, GifTooLarge, size / MB, limit / MB),
+        }
+    }
+}
+
+impl fmt::Display for TooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message(Lang::default()))
+    }
+}
+
+impl std::error::Error for TooLarge {}
+
+/// 静态贴纸的编码方式
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EncoderMode {
+    /// 有损和无损 WebP 都尝试，取满足大小限制的最小结果
+    Auto,
+    Lossy,
+    Lossless,
+    /// 经过 oxipng 优化的 PNG
+    Png,
+}
+
+impl FromStr for EncoderMode {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "auto" => Ok(EncoderMode::Auto),
+            "lossy" => Ok(EncoderMode::Lossy),
+            "lossless" => Ok(EncoderMode::Lossless),
+            "png" => Ok(EncoderMode::Png),
+            _ => Err(()),
+        }
+    }
+}
+
+/// 静态图片的编码配置
+#[derive(Clone, Copy, Debug)]
+pub struct EncoderConfig {
+    pub mode: EncoderMode,
+    /// 有损 WebP 的质量（0-100）
+    pub quality: f32,
+    /// WebP 的压缩方法（0-6），越大越慢、文件越小
+    pub method: i32,
+    /// 有损 WebP 透明通道的质量（0-100）
+    pub alpha_quality: i32,
+    /// PNG 的优化级别（0-6）
+    pub png_level: u8,
+}
+
+impl EncoderConfig {
+    /// 从环境变量读取配置：STATIC_ENCODER（auto、lossy、lossless 或 png，默认 auto）、WEBP_QUALITY（默认 90）、
+    /// WEBP_METHOD（默认 4）、WEBP_ALPHA_QUALITY（默认 100）、PNG_OPTIMIZE_LEVEL（默认 2）
+    pub fn from_env() -> Self {
+        Self {
+            mode: env_or("STATIC_ENCODER", EncoderMode::Auto),
+            quality: env_or("WEBP_QUALITY", 90.0f32).clamp(0.0, 100.0),
+            method: env_or("WEBP_METHOD", 4).clamp(0, 6),
+            alpha_quality: env_or("WEBP_ALPHA_QUALITY", 100).clamp(0, 100),
+            png_level: env_or("PNG_OPTIMIZE_LEVEL", 2).min(6),
+        }
+    }
+
+    /// 稳定的配置哈希，作为结果缓存键的一部分
+    pub fn hash(&self) -> u64 {
+        fnv1a(format!("{:?}", self).as_bytes())
+    }
+
+    /// 静态贴纸输出文件的后缀
+    pub fn suffix(&self) -> &'static str {
+        match self.mode {
+            EncoderMode::Png => ".png",
+            _ => ".webp",
+        }
+    }
+}
+
+/// 静态图片的编码后端
+#[derive(Clone, Copy, Debug)]
+enum Backend {
+    LossyWebp {
+        quality: f32,
+    },
+    LosslessWebp,
+    /// 经过 oxipng 优化的 PNG
+    Png,
+}
+
+impl Backend {
+    fn encode(self, img: &DynamicImage, config: &EncoderConfig) -> Result<Vec<u8>> {
+        match self {
+            Backend::LossyWebp { quality } => {
+                let mut webp = webp_config(config)?;
+                webp.quality = quality;
+                webp.alpha_quality = config.alpha_quality;
+                encode_webp(img, &webp)
+            }
+            Backend::LosslessWebp => {
+                let mut webp = webp_config(config)?;
+                webp.lossless = 1;
+                encode_webp(img, &webp)
+            }
+            Backend::Png => {
+                let mut png = Vec::new();
+                img.write_to(&mut Cursor::new(&mut png), ImageFormat::Png)?;
+                Ok(oxipng::optimize_from_memory(
+                    &png,
+                    &oxipng::Options::from_preset(config.png_level),
+                )?)
+            }
+        }
+    }
+}
+
+fn webp_config(config: &EncoderConfig) -> Result<WebPConfig> {
+    let mut webp = WebPConfig::new().map_err(|_| anyhow!("WebP编码配置初始化失败"))?;
+    webp.method = config.method;
+    Ok(webp)
+}
+
+fn encode_webp(img: &DynamicImage, webp: &WebPConfig) -> Result<Vec<u8>> {
+    let rgba = img.to_rgba8();
+    let memory = webp::Encoder::from_rgba(&rgba, rgba.width(), rgba.height())
+        .encode_advanced(webp)
+        .map_err(|e| anyhow!("WebP编码失败: {:?}", e))?;
+    Ok(memory.to_vec())
+}
+
+/// 按配置编码静态贴纸并检查大小限制，有损编码超出限制时逐步降低质量重试
+///
+/// 输出为 WebP 或 PNG，由 [`EncoderConfig::suffix`] 决定输出文件的后缀。
+pub fn save_static(
+    img: &DynamicImage,
+    output_path: &Path,
+    max_bytes: u64,
+    config: &EncoderConfig,
+) -> Result<()> {
+    let backends = match config.mode {
+        EncoderMode::Auto => vec![
+            Backend::LosslessWebp,
+            Backend::LossyWebp {
+                quality: config.quality,
+            },
+        ],
+        EncoderMode::Lossy => vec![Backend::LossyWebp {
+            quality: config.quality,
+        }],
+        EncoderMode::Lossless => vec![Backend::LosslessWebp],
+        EncoderMode::Png => vec![Backend::Png],
+    };
+
+    let mut smallest: Option<Vec<u8>> = None;
+    for backend in backends {
+        let data = backend.encode(img, config)?;
+        if smallest
+            .as_ref()
+            .is_none_or(|smallest| data.len() < smallest.len())
+        {
+            smallest = Some(data);
+        }
+    }
+    let mut data = smallest.ok_or_else(|| anyhow!("没有可用的编码方式"))?;
+
+    let mut quality = config.quality;
+    while data.len() as u64 > max_bytes
+        && matches!(config.mode, EncoderMode::Auto | EncoderMode::Lossy)
+        && quality > MIN_QUALITY
+    {
+        quality = (quality - QUALITY_STEP).max(MIN_QUALITY);
+        let retry = Backend::LossyWebp { quality }.encode(img, config)?;
+        if retry.len() < data.len() {
+            data = retry;
+        }
+    }
+
+    if data.len() as u64 > max_bytes {
+        return Err(TooLarge::Image {
+            size: data.len() as u64,
+            limit: max_bytes,
+        }
+        .into());
+    }
+    fs::write(output_path, data)?;
+    Ok(())
+}
+
+/// 编码为经过优化的 PNG
+pub fn save_png(img: &DynamicImage, output_path: &Path, config: &EncoderConfig) -> Result<()> {
+    fs::write(output_path, Backend::Png.encode(img, config)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{Rgba, RgbaImage};
+
+    use super::*;
+
+    fn lossy_config() -> EncoderConfig {
+        EncoderConfig {
+            mode: EncoderMode::Lossy,
+            quality: 90.0,
+            method: 4,
+            alpha_quality: 100,
+            png_level: 2,
+        }
+    }
+
+    /// 伪随机噪点，压缩率低，编码后的大小随质量明显变化
+    fn noise(width: u32, height: u32) -> DynamicImage {
+        let mut state = 0x2545_f491u32;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as u8
+        };
+        DynamicImage::ImageRgba8(RgbaImage::from_fn(width, height, |_, _| {
+            Rgba([next(), next(), next(), 255])
+        }))
+    }
+
+    fn encoded_size(img: &DynamicImage, quality: f32, config: &EncoderConfig) -> u64 {
+        Backend::LossyWebp { quality }
+            .encode(img, config)
+            .unwrap()
+            .len() as u64
+    }
+
+    #[test]
+    fn hash_changes_with_settings() {
+        let config = lossy_config();
+        let png = EncoderConfig {
+            mode: EncoderMode::Png,
+            ..config
+        };
+        let quality = EncoderConfig {
+            quality: 80.0,
+            ..config
+        };
+        assert_eq!(config.hash(), lossy_config().hash());
+        assert_ne!(config.hash(), png.hash());
+        assert_ne!(config.hash(), quality.hash());
+    }
+
+    #[test]
+    fn save_static_lowers_quality_to_fit() {
+        let config = lossy_config();
+        let img = noise(128, 128);
+        let initial = encoded_size(&img, config.quality, &config);
+        let lowest = encoded_size(&img, MIN_QUALITY, &config);
+        assert!(lowest < initial);
+
+        let limit = (initial + lowest) / 2;
+        let output = tempfile::NamedTempFile::new().unwrap();
+        save_static(&img, output.path(), limit, &config).unwrap();
+        let size = fs::metadata(output.path()).unwrap().len();
+        assert!(size <= limit && size < initial, "{} > {}", size, limit);
+    }
+
+    #[test]
+    fn save_static_reports_too_large() {
+        let config = lossy_config();
+        let img = noise(128, 128);
+        let limit = encoded_size(&img, MIN_QUALITY, &config) - 1;
+        let output = tempfile::NamedTempFile::new().unwrap();
+
+        let e = save_static(&img, output.path(), limit, &config).unwrap_err();
+        match e.downcast_ref::<TooLarge>() {
+            Some(&TooLarge::Image {
+                size,
+                limit: actual,
+            }) => {
+                assert_eq!(actual, limit);
+                assert!(size > limit);
+            }
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(fs::metadata(output.path()).unwrap().len(), 0);
+    }
+}
@@ -0,0 +1,364 @@
+use std::fs;
+use std::io::Write;
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use teloxide::prelude::*;
+use teloxide::types::{InputFile, Sticker, StickerFormat, StickerSet};
+use tempfile::NamedTempFile;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::auth::SharedAuth;
+use crate::encode::EncoderConfig;
+use crate::handlers::{limited_user, reject_if_limited};
+use crate::i18n::Lang;
+use crate::limits::{SharedLimiter, UserQuota};
+use crate::media::{Failure, MAX_OUTPUT_SIZE, download_file, error_message, output_tempfile};
+use crate::processors::{process_image_to_png, process_tgs_to_gif, process_video_to_gif};
+use crate::reply::ReplyToExt;
+use crate::retry::RetryAfterExt;
+use crate::tr;
+
+/// 每处理多少个贴纸更新一次进度
+const PROGRESS_STEP: usize = 10;
+
+/// 清单中的单个贴纸
+#[derive(Debug, Serialize)]
+struct ExportEntry {
+    /// 在贴纸包中的位置，从 1 开始
+    index: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    emoji: Option<String>,
+    file_unique_id: String,
+    /// 原始格式：static / animated / video
+    format: &'static str,
+    /// 该贴纸在压缩包中的文件
+    files: Vec<String>,
+    /// "ok" 或 "error"
+    status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    note: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// 导出压缩包中的清单
+#[derive(Debug, Serialize)]
+struct ExportManifest {
+    name: String,
+    title: String,
+    sticker_type: String,
+    count: usize,
+    succeeded: usize,
+    failed: usize,
+    stickers: Vec<ExportEntry>,
+}
+
+/// 从命令参数或被回复的贴纸中取出贴纸包名称
+///
+/// 参数可以是贴纸包名称，也可以是 `t.me/addstickers/<名称>` 或 `t.me/addemoji/<名称>` 链接。
+fn requested_set_name(msg: &Message, args: &str) -> Option<String> {
+    let args = args.trim();
+    if args.is_empty() {
+        return msg
+            .reply_to_message()
+            .and_then(|reply| reply.sticker())
+            .and_then(|sticker| sticker.set_name.clone());
+    }
+    parse_set_name(args)
+}
+
+/// 解析贴纸包名称，支持 `t.me/addstickers/<名称>` 和 `t.me/addemoji/<名称>` 链接
+pub fn parse_set_name(arg: &str) -> Option<String> {
+    let name = arg
+        .trim()
+        .trim_start_matches("https://")
+        .trim_start_matches("http://")
+        .trim_start_matches("t.me/addstickers/")
+        .trim_start_matches("t.me/addemoji/")
+        .split(['?', '/'])
+        .next()
+        .unwrap_or_default();
+    Some(name.to_string()).filter(|name| !name.is_empty())
+}
+
+fn format_name(format: StickerFormat) -> &'static str {
+    match format {
+        StickerFormat::Static => "static",
+        StickerFormat::Animated => "animated",
+        StickerFormat::Video => "video",
+    }
+}
+
+/// 下载并转换单个贴纸，返回要写入压缩包的文件及备注
+///
+/// 静态贴纸转为 PNG，视频贴纸和 TGS 动画贴纸保留原始文件并附带 GIF。
+/// TGS 是 Lottie 动画，由外部工具渲染（见 [`process_tgs_to_gif`]），未开启 `tgs` feature 时只保留原始文件。
+async fn export_sticker(
+    bot: &Bot,
+    sticker: &Sticker,
+    base_name: &str,
+    encoder: &EncoderConfig,
+    lang: Lang,
+) -> Result<(Vec<(String, Vec<u8>)>, Option<String>)> {
+    let (input, _) = download_file(bot, sticker.file.id.clone()).await?;
+    match sticker.format() {
+        StickerFormat::Static => {
+            let output = output_tempfile(".png")?;
+            process_image_to_png(input.path(), output.path(), encoder)
+                .await
+                .context("PNG转换失败")?;
+            Ok((
+                vec![(format!("{}.png", base_name), fs::read(output.path())?)],
+                None,
+            ))
+        }
+        format @ (StickerFormat::Video | StickerFormat::Animated) => {
+            let extension = match format {
+                StickerFormat::Animated => "tgs",
+                _ => "webm",
+            };
+            let mut files = vec![(
+                format!("{}.{}", base_name, extension),
+                fs::read(input.path())?,
+            )];
+            if format == StickerFormat::Animated && !cfg!(feature = "tgs") {
+                return Ok((files, Some(tr!(lang, ExportTgsNote))));
+            }
+            let output = output_tempfile(".gif")?;
+            let result = match format {
+                StickerFormat::Animated => process_tgs_to_gif(input.path(), output.path()).await,
+                _ => process_video_to_gif(input.path(), output.path()).await,
+            };
+            let note = match result {
+                Ok(()) => {
+                    files.push((format!("{}.gif", base_name), fs::read(output.path())?));
+                    None
+                }
+                Err(e) => {
+                    log::warn!("贴纸 {} 的GIF转换失败: {:?}", sticker.file.unique_id, e);
+                    Some(tr!(
+                        lang,
+                        ExportGifFailed,
+                        extension.to_uppercase(),
+                        error_message(&e, lang)
+                    ))
+                }
+            };
+            Ok((files, note))
+        }
+    }
+}
+
+/// 逐个下载并转换贴纸包中的贴纸，将结果与清单 manifest.json 打包
+///
+/// 每处理 [`PROGRESS_STEP`] 个贴纸编辑一次进度消息。每个贴纸转换前检查 `quota` 的每日转换次数，
+/// 用完后剩余的贴纸记为失败。
+async fn export_set_archive(
+    bot: &Bot,
+    set: &StickerSet,
+    progress: &Message,
+    encoder: &EncoderConfig,
+    lang: Lang,
+    quota: Option<UserQuota<'_>>,
+) -> Result<(NamedTempFile, ExportManifest)> {
+    let output = output_tempfile(".zip")?;
+    let mut writer = ZipWriter::new(output.reopen()?);
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    let mut manifest = ExportManifest {
+        name: set.name.clone(),
+        title: set.title.clone(),
+        sticker_type: format!("{:?}", set.kind),
+        count: set.stickers.len(),
+        succeeded: 0,
+        failed: 0,
+        stickers: Vec::with_capacity(set.stickers.len()),
+    };
+
+    for (index, sticker) in set.stickers.iter().enumerate() {
+        let position = index + 1;
+        let mut entry = ExportEntry {
+            index: position,
+            emoji: sticker.emoji.clone(),
+            file_unique_id: sticker.file.unique_id.to_string(),
+            format: format_name(sticker.format()),
+            files: Vec::new(),
+            status: "error",
+            note: None,
+            error: None,
+        };
+        let result = match quota.map(|quota| quota.check_next(manifest.succeeded as u32)) {
+            Some(Err(e)) => Err(Failure::Localized(e.message(lang)).into()),
+            _ => export_sticker(bot, sticker, &format!("{:03}", position), encoder, lang).await,
+        };
+        match result {
+            Ok((files, note)) => {
+                for (name, data) in files {
+                    writer.start_file(name.as_str(), options)?;
+                    writer.write_all(&data)?;
+                    entry.files.push(name);
+                }
+                entry.note = note;
+                entry.status = "ok";
+                manifest.succeeded += 1;
+            }
+            Err(e) => {
+                log::warn!(
+                    "贴纸包 {} 的第 {} 个贴纸导出失败: {:?}",
+                    set.name,
+                    position,
+                    e
+                );
+                entry.error = Some(error_message(&e, lang));
+                manifest.failed += 1;
+            }
+        }
+        manifest.stickers.push(entry);
+
+        if position % PROGRESS_STEP == 0 && position < set.stickers.len() {
+            let text = tr!(
+                lang,
+                ExportProgress,
+                set.title,
+                position,
+                set.stickers.len()
+            );
+            if let Err(e) = bot
+                .edit_message_text(progress.chat.id, progress.id, text)
+                .send_retry()
+                .await
+            {
+                log::warn!("更新导出进度失败: {}", e);
+            }
+        }
+    }
+
+    writer.start_file("manifest.json", options)?;
+    writer.write_all(serde_json::to_string_pretty(&manifest)?.as_bytes())?;
+    writer.finish()?;
+
+    let output_size = fs::metadata(output.path())?.len();
+    if output_size > MAX_OUTPUT_SIZE {
+        return Err(Failure::Localized(tr!(
+            lang,
+            ResultArchiveTooLarge,
+            output_size / (1024 * 1024)
+        ))
+        .into());
+    }
+    Ok((output, manifest))
+}
+
+/// /exportpack [名称或链接]：导出整个贴纸包为压缩包，未指定时使用被回复贴纸所在的贴纸包
+pub async fn export_pack(
+    bot: &Bot,
+    msg: &Message,
+    args: &str,
+    auth: &SharedAuth,
+    limiter: &SharedLimiter,
+    encoder: &EncoderConfig,
+    lang: Lang,
+) -> Result<()> {
+    let Some(name) = requested_set_name(msg, args) else {
+        bot.send_message(msg.chat.id, tr!(lang, ExportPackUsage))
+            .reply_to(msg)
+            .send_retry()
+            .await?;
+        return Ok(());
+    };
+
+    let set = match bot.get_sticker_set(&name).send_retry().await {
+        Ok(set) => set,
+        Err(e) => {
+            log::warn!("获取贴纸包 {} 失败: {}", name, e);
+            bot.send_message(msg.chat.id, tr!(lang, SetNotFound, name))
+                .reply_to(msg)
+                .send_retry()
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let total_size: u64 = set.stickers.iter().map(|s| s.file.size as u64).sum();
+    let user_id = limited_user(msg, auth);
+    if reject_if_limited(bot, msg, user_id, total_size, limiter, lang).await? {
+        return Ok(());
+    }
+
+    log::info!(
+        "ChatID: {}, 开始导出贴纸包 {}，共 {} 个贴纸",
+        msg.chat.id,
+        set.name,
+        set.stickers.len()
+    );
+    let progress = bot
+        .send_message(
+            msg.chat.id,
+            tr!(lang, ExportProgress, set.title, 0, set.stickers.len()),
+        )
+        .reply_to(msg)
+        .send_retry()
+        .await?;
+
+    let quota = user_id.map(|user_id| UserQuota { limiter, user_id });
+    let (archive, manifest) =
+        match export_set_archive(bot, &set, &progress, encoder, lang, quota).await {
+            Ok(result) => result,
+            Err(e) => {
+                log::error!("贴纸包 {} 导出失败: {:?}", set.name, e);
+                bot.edit_message_text(
+                    progress.chat.id,
+                    progress.id,
+                    tr!(lang, ExportFailed, error_message(&e, lang)),
+                )
+                .send_retry()
+                .await?;
+                return Ok(());
+            }
+        };
+    if let Some(user_id) = user_id {
+        limiter
+            .record_batch(user_id, manifest.succeeded as u32, total_size)
+            .await?;
+    }
+
+    let mut caption = tr!(
+        lang,
+        ExportDone,
+        set.title,
+        manifest.succeeded,
+        manifest.failed
+    );
+    // TGS 动画贴纸未能转换为 GIF 时只有原始文件，需要明确告知用户
+    let tgs_kept = manifest
+        .stickers
+        .iter()
+        .filter(|entry| {
+            entry.format == "animated"
+                && entry.status == "ok"
+                && !entry.files.iter().any(|file| file.ends_with(".gif"))
+        })
+        .count();
+    if tgs_kept > 0 {
+        caption.push_str(&tr!(lang, ExportTgsKept, tgs_kept));
+    }
+
+    bot.edit_message_text(
+        progress.chat.id,
+        progress.id,
+        tr!(lang, ExportUploading, set.title),
+    )
+    .send_retry()
+    .await?;
+    bot.send_document(
+        msg.chat.id,
+        InputFile::file(archive.path()).file_name(format!("{}.zip", set.name)),
+    )
+    .disable_content_type_detection(true)
+    .caption(caption)
+    .reply_to(msg)
+    .send_retry()
+    .await?;
+    Ok(())
+}
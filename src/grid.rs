@@ -0,0 +1,68 @@
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use anyhow::Result;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::encode::EncoderConfig;
+use crate::i18n::Lang;
+use crate::media::{Converted, DetectedType, Failure, output_tempfile};
+use crate::options::{Grid, ProcessOptions};
+use crate::processors::{process_emoji_grid_image, process_emoji_grid_webm};
+use crate::tr;
+
+/// 按网格切分并转换为自定义表情，逐行从左到右排列
+pub async fn convert_tiles(
+    input_path: &Path,
+    detected: &DetectedType,
+    grid: &Grid,
+    options: &ProcessOptions,
+    encoder: &EncoderConfig,
+    lang: Lang,
+) -> Result<Vec<Converted>> {
+    let tiles = if detected.is_image {
+        process_emoji_grid_image(input_path, grid, options, encoder).await?
+    } else if detected.is_video {
+        process_emoji_grid_webm(input_path, grid, options).await?
+    } else {
+        return Err(Failure::Localized(tr!(lang, UnsupportedForSticker, detected.mime)).into());
+    };
+    Ok(tiles
+        .into_iter()
+        .map(|tile| Converted::new(tile, true))
+        .collect())
+}
+
+/// 切分为表情网格并打包，文件名按顺序编号并标出所在的行和列
+pub async fn convert_grid(
+    input_path: &Path,
+    detected: &DetectedType,
+    grid: &Grid,
+    options: &ProcessOptions,
+    encoder: &EncoderConfig,
+    lang: Lang,
+) -> Result<Converted> {
+    let tiles = convert_tiles(input_path, detected, grid, options, encoder, lang).await?;
+
+    let output = output_tempfile(".zip")?;
+    let mut writer = ZipWriter::new(output.reopen()?);
+    let zip_options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    for (index, tile) in tiles.iter().enumerate() {
+        let (row, column) = (index as u32 / grid.columns, index as u32 % grid.columns);
+        let extension = tile
+            .path
+            .extension()
+            .map(|extension| extension.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let name = format!("{:02}_r{}c{}.{}", index + 1, row + 1, column + 1, extension);
+        writer.start_file(name, zip_options)?;
+        writer.write_all(&fs::read(&tile.path)?)?;
+    }
+    writer.finish()?;
+
+    let mut converted = Converted::new(output, false);
+    converted.caption = Some(tr!(lang, GridDone, grid.columns, grid.rows));
+    Ok(converted)
+}
@@ -1,12 +1,30 @@
-use anyhow::Context;
+use std::collections::HashMap;
+
 use teloxide::prelude::*;
-use teloxide::types::ChatId;
+use teloxide::types::{FileMeta, InputFile, InputMedia, InputMediaDocument, Me, UserId};
 use teloxide::utils::command::BotCommands;
-use tempfile::Builder;
-use tokio::fs as tokio_fs;
 
-use crate::processors::{process_image, process_video_to_gif, process_webm};
-use crate::state::{Mode, ModeState, get_chat_mode, toggle_chat_mode};
+use crate::album::SharedAlbums;
+use crate::auth::{RuleTarget, SharedAuth};
+use crate::cache::{CachedResult, SharedCache};
+use crate::encode::EncoderConfig;
+use crate::history::{HistoryEntry, SharedHistory};
+use crate::i18n::{Lang, Msg, fill};
+use crate::limits::{SharedLimiter, UserQuota};
+use crate::media::{
+    Converted, Target, convert, detect_type, download_file, error_message, extract_media_file,
+    media_dimensions,
+};
+use crate::metadata::StickerMeta;
+use crate::options::{BackgroundMode, BackgroundRemoval, ProcessOptions, parse_background};
+use crate::processors::probe_video;
+use crate::reply::{ReplyToExt, is_addressed_to_bot, is_group_chat};
+use crate::retry::RetryAfterExt;
+use crate::state::{
+    Mode, ModeState, SharedLangs, get_chat_mode, get_chat_options, toggle_chat_mode,
+    update_chat_options,
+};
+use crate::tr;
 
 #[derive(BotCommands, Clone)]
 #[command(rename_rule = "lowercase", description = "支持的命令：")]
@@ -17,274 +35,1099 @@ pub enum BotCommand {
     Start,
     #[command(description = "切换工作模式")]
     Mode,
+    #[command(description = "查看今日用量")]
+    Quota,
+    #[command(description = "切换语言：/lang <zh|en|auto>")]
+    Lang(String),
+    #[command(description = "回复一条消息，将其中的媒体转为贴纸")]
+    Sticker,
+    #[command(description = "回复一条消息，将其中的媒体转为 GIF")]
+    Gif,
+    #[command(description = "回复一条消息，将其中的媒体转为自定义表情 (100x100)")]
+    Emoji,
+    #[command(description = "回复一条消息，查看其中媒体的信息")]
+    Info,
+    #[command(description = "设置本聊天是否去除图片背景：/removebg <auto|#RRGGBB|off>")]
+    RemoveBg(String),
+    #[command(description = "按表情或关键词搜索处理过的贴纸：/search <表情或关键词>")]
+    Search(String),
 }
 
-pub async fn send_welcome_message(bot: Bot, chat_id: ChatId, mode: Mode) -> anyhow::Result<()> {
+#[derive(BotCommands, Clone)]
+#[command(rename_rule = "lowercase", description = "管理员命令：")]
+pub enum AdminCommand {
+    #[command(description = "加入白名单（<id>、user:<id> 或 member:<群组id>）")]
+    Allow(String),
+    #[command(description = "移出白名单")]
+    Deny(String),
+    #[command(description = "加入黑名单（<id> 或 user:<id>）")]
+    Block(String),
+    #[command(description = "移出黑名单")]
+    Unblock(String),
+    #[command(description = "查看访问规则")]
+    Allowed,
+}
+
+pub async fn send_welcome_message(
+    bot: Bot,
+    msg: &Message,
+    mode: Mode,
+    lang: Lang,
+) -> anyhow::Result<()> {
     let mode_info = match mode {
-        Mode::StickerOptimize => {
-            "📦 **贴纸优化模式**\n\
-            将图片和视频转为 Telegram 贴纸格式\n\
-            - 图片 → WebP 贴纸\n\
-            - 视频 → VP9 WebM 贴纸"
-        }
-        Mode::GifDownload => {
-            "🎞️ **GIF 下载模式**\n\
-            将视频转为 GIF 文件返回\n\
-            - 视频 → GIF\n\
-            - 动态贴纸 → GIF\n\
-            - 动图 → GIF\n\
-            - 图片 → 作为文档发送"
-        }
+        Mode::StickerOptimize => Msg::WelcomeStickerOptimize.text(lang),
+        Mode::GifDownload => Msg::WelcomeGifDownload.text(lang),
     };
+    let message = tr!(lang, Welcome, mode.label(lang), mode_info);
 
-    let message = format!(
-        "欢迎使用 Telegram Sticker 工具！\n\n\
-        **当前模式**: {}\n\n\
-        {}\n\n\
-        使用 /mode 切换工作模式。",
-        mode, mode_info
-    );
+    bot.send_message(msg.chat.id, message)
+        .reply_to(msg)
+        .send_retry()
+        .await?;
+    Ok(())
+}
 
-    bot.send_message(chat_id, message).await?;
+/// 处理 /lang：显示或切换用户的语言
+async fn switch_lang(
+    bot: &Bot,
+    msg: &Message,
+    arg: &str,
+    lang: Lang,
+    langs: &SharedLangs,
+) -> anyhow::Result<()> {
+    let Some(user) = msg.from.as_ref() else {
+        return Ok(());
+    };
+    let arg = arg.trim();
+    let message = if arg.is_empty() {
+        tr!(lang, LangCurrent)
+    } else if arg.eq_ignore_ascii_case("auto") {
+        langs.set(user.id, None).await?;
+        tr!(lang, LangAuto)
+    } else {
+        match arg.parse::<Lang>() {
+            Ok(lang) => {
+                langs.set(user.id, Some(lang)).await?;
+                tr!(lang, LangSwitched)
+            }
+            Err(()) => tr!(lang, LangUnknown, arg),
+        }
+    };
+    bot.send_message(msg.chat.id, message)
+        .reply_to(msg)
+        .send_retry()
+        .await?;
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn command_handler(
     bot: Bot,
     msg: Message,
     cmd: BotCommand,
     mode_state: ModeState,
+    auth: SharedAuth,
+    limiter: SharedLimiter,
+    history: SharedHistory,
+    cache: SharedCache,
+    encoder: EncoderConfig,
+    lang: Lang,
+    langs: SharedLangs,
 ) -> anyhow::Result<()> {
     match cmd {
         BotCommand::Help | BotCommand::Start => {
             let mode = get_chat_mode(&mode_state, msg.chat.id);
-            send_welcome_message(bot, msg.chat.id, mode).await?;
+            send_welcome_message(bot, &msg, mode, lang).await?;
         }
         BotCommand::Mode => {
             let new_mode = toggle_chat_mode(&mode_state, msg.chat.id);
             let extra = match new_mode {
-                Mode::StickerOptimize => "现在可以发送图片或视频，我将处理成贴纸格式。",
-                Mode::GifDownload => "现在可以发送视频、动图、动态贴纸或图片，我将返回 GIF 文件或原图。",
+                Mode::StickerOptimize => Msg::ModeSwitchedSticker.text(lang),
+                Mode::GifDownload => Msg::ModeSwitchedGif.text(lang),
             };
-            let message = format!("✅ 已切换到 **{}**\n\n{}", new_mode, extra);
-            bot.send_message(msg.chat.id, message).await?;
+            let message = tr!(lang, ModeSwitched, new_mode.label(lang), extra);
+            bot.send_message(msg.chat.id, message)
+                .reply_to(&msg)
+                .send_retry()
+                .await?;
+        }
+        BotCommand::Quota => {
+            let Some(user) = msg.from.as_ref() else {
+                return Ok(());
+            };
+            let config = limiter.config();
+            let usage = limiter.usage(user.id);
+            let limit_str = |used: u64, limit: u64, unit: &str| {
+                if limit == 0 {
+                    tr!(lang, QuotaUnlimited, used, unit)
+                } else {
+                    format!("{}{} / {}{}", used, unit, limit, unit)
+                }
+            };
+            let message = tr!(
+                lang,
+                Quota,
+                limit_str(
+                    usage.conversions as u64,
+                    config.daily_conversions as u64,
+                    ""
+                ),
+                limit_str(
+                    usage.bytes / (1024 * 1024),
+                    config.daily_bytes / (1024 * 1024),
+                    "MB"
+                ),
+                config.per_minute,
+                config.burst
+            );
+            bot.send_message(msg.chat.id, message)
+                .reply_to(&msg)
+                .send_retry()
+                .await?;
+        }
+        BotCommand::Lang(arg) => switch_lang(&bot, &msg, &arg, lang, &langs).await?,
+        BotCommand::Sticker | BotCommand::Gif | BotCommand::Emoji => {
+            let target = match cmd {
+                BotCommand::Gif => Target::Gif,
+                BotCommand::Emoji => Target::Emoji,
+                _ => Target::Sticker,
+            };
+            let options = get_chat_options(&mode_state, msg.chat.id);
+            process_replied(
+                &bot, &msg, target, options, &auth, &limiter, &history, &cache, &encoder, lang,
+            )
+            .await?;
+        }
+        BotCommand::Search(query) => {
+            send_search_results(&bot, &msg, &query, &history, lang).await?
+        }
+        BotCommand::Info => send_media_info(&bot, &msg, lang).await?,
+        BotCommand::RemoveBg(arg) => {
+            set_chat_background(&bot, &msg, &arg, &mode_state, lang).await?
         }
     }
     Ok(())
 }
 
+/// 处理 /removebg：设置本聊天默认是否去除图片背景
+async fn set_chat_background(
+    bot: &Bot,
+    msg: &Message,
+    arg: &str,
+    mode_state: &ModeState,
+    lang: Lang,
+) -> anyhow::Result<()> {
+    let arg = arg.trim();
+    let message = if arg.is_empty() {
+        match get_chat_options(mode_state, msg.chat.id).background {
+            Some(removal) => tr!(lang, RemoveBgStatus, background_label(&removal)),
+            None => tr!(lang, RemoveBgUsage),
+        }
+    } else {
+        match parse_background(arg) {
+            Some(mode) => {
+                update_chat_options(mode_state, msg.chat.id, |options| {
+                    options.background = mode.map(BackgroundRemoval::new);
+                });
+                match mode {
+                    Some(mode) => tr!(
+                        lang,
+                        RemoveBgEnabled,
+                        background_label(&BackgroundRemoval::new(mode))
+                    ),
+                    None => tr!(lang, RemoveBgDisabled),
+                }
+            }
+            None => tr!(lang, InvalidOption, arg),
+        }
+    };
+    bot.send_message(msg.chat.id, message)
+        .reply_to(msg)
+        .send_retry()
+        .await?;
+    Ok(())
+}
+
+/// 背景去除方式的说明，例如 `auto` 或 `#ffffff`
+fn background_label(removal: &BackgroundRemoval) -> String {
+    match removal.mode {
+        BackgroundMode::Auto => "auto".to_string(),
+        BackgroundMode::Color([r, g, b]) => format!("#{:02x}{:02x}{:02x}", r, g, b),
+    }
+}
+
+/// 将 ID 列表格式化为逗号分隔的字符串
+fn join_ids<T: std::fmt::Display>(ids: &[T], lang: Lang) -> String {
+    if ids.is_empty() {
+        return tr!(lang, Empty);
+    }
+    ids.iter()
+        .map(|id| id.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// 解析规则对象并执行修改，返回回复文本
+async fn update_rule<F: Future<Output = anyhow::Result<bool>>>(
+    arg: &str,
+    lang: Lang,
+    apply: impl FnOnce(RuleTarget) -> F,
+    changed: Msg,
+    unchanged: Msg,
+) -> anyhow::Result<String> {
+    let target: RuleTarget = match arg.parse() {
+        Ok(target) => target,
+        Err(e) => {
+            log::info!("无效的规则 '{}': {}", arg, e);
+            return Ok(tr!(lang, InvalidRule, arg.trim()));
+        }
+    };
+    let message = if apply(target).await? {
+        log::info!("管理员修改规则: {} ({:?})", target, changed);
+        changed
+    } else {
+        unchanged
+    };
+    Ok(fill(message.text(lang), &[&target.label(lang)]))
+}
+
+pub async fn admin_command_handler(
+    bot: Bot,
+    msg: Message,
+    cmd: AdminCommand,
+    auth: SharedAuth,
+    lang: Lang,
+) -> anyhow::Result<()> {
+    let message = match cmd {
+        AdminCommand::Allow(arg) => {
+            update_rule(
+                &arg,
+                lang,
+                |t| auth.allow(t),
+                Msg::RuleAllowed,
+                Msg::RuleAlreadyAllowed,
+            )
+            .await?
+        }
+        AdminCommand::Deny(arg) => {
+            update_rule(
+                &arg,
+                lang,
+                |t| auth.deny(t),
+                Msg::RuleDenied,
+                Msg::RuleNotAllowed,
+            )
+            .await?
+        }
+        AdminCommand::Block(arg) => {
+            update_rule(
+                &arg,
+                lang,
+                |t| auth.block(t),
+                Msg::RuleBlocked,
+                Msg::RuleAlreadyBlocked,
+            )
+            .await?
+        }
+        AdminCommand::Unblock(arg) => {
+            update_rule(
+                &arg,
+                lang,
+                |t| auth.unblock(t),
+                Msg::RuleUnblocked,
+                Msg::RuleNotBlocked,
+            )
+            .await?
+        }
+        AdminCommand::Allowed => {
+            let rules = auth.rules();
+            let whitelist = if rules.whitelist_enabled {
+                tr!(lang, WhitelistEnabled)
+            } else {
+                tr!(lang, WhitelistDisabled)
+            };
+            tr!(
+                lang,
+                AccessRules,
+                whitelist,
+                join_ids(&rules.allowed_chats, lang),
+                join_ids(&rules.allowed_users, lang),
+                join_ids(&rules.member_of, lang),
+                join_ids(&rules.denied_chats, lang),
+                join_ids(&rules.denied_users, lang),
+            )
+        }
+    };
+    bot.send_message(msg.chat.id, message)
+        .reply_to(&msg)
+        .send_retry()
+        .await?;
+    Ok(())
+}
+
 pub async fn unhandled_message_handler(
     bot: Bot,
     msg: Message,
     mode_state: ModeState,
+    lang: Lang,
 ) -> anyhow::Result<()> {
+    // 群组中不回复未呼叫机器人的消息，避免刷屏
+    if is_group_chat(&msg) {
+        return Ok(());
+    }
     let mode = get_chat_mode(&mode_state, msg.chat.id);
-    send_welcome_message(bot, msg.chat.id, mode).await?;
+    send_welcome_message(bot, &msg, mode, lang).await?;
     Ok(())
 }
 
-pub async fn unauthorized_access_handler(bot: Bot, msg: Message) -> anyhow::Result<()> {
+pub async fn unauthorized_access_handler(
+    bot: Bot,
+    msg: Message,
+    me: Me,
+    lang: Lang,
+) -> anyhow::Result<()> {
     log::warn!("ChatID: {} - Unauthorized access attempt.", msg.chat.id);
-    bot.send_message(msg.chat.id, "抱歉，您未被授权使用此机器人。")
+    // 群组中只在呼叫机器人时提示，发给其他机器人的命令不回复
+    if is_group_chat(&msg) && !is_addressed_to_bot(&msg, &me) {
+        return Ok(());
+    }
+    bot.send_message(msg.chat.id, tr!(lang, Unauthorized))
+        .reply_to(&msg)
+        .send_retry()
         .await?;
     Ok(())
 }
 
-pub async fn handle_file(bot: Bot, msg: Message, mode_state: ModeState) -> anyhow::Result<()> {
-    log::info!("ChatID: {}, Received New message", msg.chat.id);
+/// 需要限流的用户（管理员不受限制）
+pub fn limited_user(msg: &Message, auth: &SharedAuth) -> Option<UserId> {
+    msg.from
+        .as_ref()
+        .map(|user| user.id)
+        .filter(|id| !auth.is_admin(*id))
+}
 
-    let file_id = if let Some(photo) = msg.photo() {
-        photo.last().expect("照片列表不应为空").file.id.clone()
-    } else if let Some(document) = msg.document() {
-        match document
-            .mime_type
-            .as_ref()
-            .map(|mime| mime.to_string())
-            .as_deref()
-        {
-            Some(mime)
-                if mime.starts_with("image/")
-                    || mime.starts_with("video/")
-                    || mime == "application/octet-stream" =>
-            {
-                document.file.id.clone()
+/// 检查限流与配额，被限制时回复提示并返回 true
+pub async fn reject_if_limited(
+    bot: &Bot,
+    msg: &Message,
+    user_id: Option<UserId>,
+    bytes: u64,
+    limiter: &SharedLimiter,
+    lang: Lang,
+) -> anyhow::Result<bool> {
+    reject_if_limited_batch(bot, msg, user_id, 1, bytes, limiter, lang).await
+}
+
+/// 检查一次发起 `conversions` 次转换的限流与配额，被限制时回复提示并返回 true
+pub async fn reject_if_limited_batch(
+    bot: &Bot,
+    msg: &Message,
+    user_id: Option<UserId>,
+    conversions: u32,
+    bytes: u64,
+    limiter: &SharedLimiter,
+    lang: Lang,
+) -> anyhow::Result<bool> {
+    let Some(user_id) = user_id else {
+        return Ok(false);
+    };
+    let Err(e) = limiter.check_batch(user_id, conversions, bytes) else {
+        return Ok(false);
+    };
+    log::info!("ChatID: {}, 用户 {} 被限流: {:?}", msg.chat.id, user_id, e);
+    bot.send_message(msg.chat.id, e.message(lang))
+        .reply_to(msg)
+        .send_retry()
+        .await?;
+    Ok(true)
+}
+
+/// 下载并转换文件，返回结果和输入文件大小
+pub async fn download_and_convert(
+    bot: &Bot,
+    file: FileMeta,
+    target: Target,
+    options: &ProcessOptions,
+    encoder: &EncoderConfig,
+    lang: Lang,
+    quota: Option<UserQuota<'_>>,
+) -> anyhow::Result<(Converted, u64)> {
+    let (input_temp_file, input_size) = download_file(bot, file.id).await?;
+    let converted = convert(input_temp_file, target, options, encoder, lang, quota).await?;
+    Ok((converted, input_size))
+}
+
+/// 一项待处理的媒体：文件、处理目标、处理选项以及说明文字中的表情和关键词
+pub struct MediaJob {
+    pub file: FileMeta,
+    pub target: Target,
+    pub options: ProcessOptions,
+    pub meta: StickerMeta,
+}
+
+impl MediaJob {
+    /// 从说明文字所在的消息创建：表情、关键词和处理选项取自说明文字，选项覆盖聊天的默认选项
+    ///
+    /// 选项无效时返回给用户的提示。
+    pub fn new(
+        file: FileMeta,
+        target: Target,
+        source: &Message,
+        mut options: ProcessOptions,
+        lang: Lang,
+    ) -> Result<Self, String> {
+        if let Some(caption) = source.caption() {
+            options
+                .apply_text(caption)
+                .map_err(|word| tr!(lang, InvalidOption, word))?;
+        }
+        Ok(Self {
+            file,
+            target,
+            options,
+            meta: StickerMeta::from_message(source),
+        })
+    }
+
+    /// 结果缓存的键：(file_unique_id, 处理目标, 选项哈希, 编码配置哈希)
+    ///
+    /// 编码配置改变后（如切换 STATIC_ENCODER）不再命中之前的结果。
+    pub fn cache_key(&self, encoder: &EncoderConfig) -> String {
+        format!(
+            "{}:{:?}:{:016x}:{:016x}",
+            self.file.unique_id,
+            self.target,
+            self.options.hash(),
+            encoder.hash()
+        )
+    }
+}
+
+/// 待发送的结果：新转换的文件，或缓存中已发送过的 file_id
+enum Output {
+    Converted {
+        converted: Converted,
+        input_size: u64,
+    },
+    Cached(CachedResult),
+}
+
+impl Output {
+    fn input_file(&self) -> InputFile {
+        match self {
+            Output::Converted { converted, .. } => InputFile::file(&converted.path),
+            Output::Cached(cached) => InputFile::file_id(cached.file_id.clone().into()),
+        }
+    }
+
+    fn is_sticker(&self) -> bool {
+        match self {
+            Output::Converted { converted, .. } => converted.is_sticker,
+            Output::Cached(cached) => cached.is_sticker,
+        }
+    }
+
+    fn caption(&self) -> Option<&String> {
+        match self {
+            Output::Converted { converted, .. } => converted.caption.as_ref(),
+            Output::Cached(cached) => cached.caption.as_ref(),
+        }
+    }
+
+    /// 新转换结果计入配额的转换次数和输入文件大小，缓存命中时为 None，不计入配额
+    fn usage(&self) -> Option<(u32, u64)> {
+        match self {
+            Output::Converted {
+                converted,
+                input_size,
+            } => Some((converted.conversions, *input_size)),
+            Output::Cached(_) => None,
+        }
+    }
+}
+
+/// 发送结果的 file_id 以及是否为贴纸
+fn sent_file(sent: &Message) -> Option<(String, bool)> {
+    sent.sticker()
+        .map(|sticker| (sticker.file.id.to_string(), true))
+        .or_else(|| {
+            sent.document()
+                .map(|document| (document.file.id.to_string(), false))
+        })
+}
+
+/// 将发送的结果记入请求者的处理历史，新转换的结果同时写入缓存
+async fn record_sent(
+    history: &SharedHistory,
+    cache: &SharedCache,
+    msg: &Message,
+    job: &MediaJob,
+    output: &Output,
+    sent: &Message,
+    encoder: &EncoderConfig,
+) -> anyhow::Result<()> {
+    let Some((file_id, is_sticker)) = sent_file(sent) else {
+        return Ok(());
+    };
+    if matches!(output, Output::Converted { .. }) {
+        cache
+            .insert(
+                job.cache_key(encoder),
+                file_id.clone(),
+                is_sticker,
+                output.caption().cloned(),
+            )
+            .await?;
+    }
+    let Some(user) = msg.from.as_ref() else {
+        return Ok(());
+    };
+    history
+        .record(
+            user.id,
+            HistoryEntry::new(
+                msg.chat.id,
+                job.file.unique_id.to_string(),
+                job.target,
+                file_id,
+                is_sticker,
+                job.meta.clone(),
+            ),
+        )
+        .await
+}
+
+/// 以回复 `msg` 的形式发送单个结果
+async fn send_output(
+    bot: &Bot,
+    msg: &Message,
+    job: &MediaJob,
+    output: &Output,
+) -> Result<Message, teloxide::RequestError> {
+    if output.is_sticker() {
+        let mut request = bot
+            .send_sticker(msg.chat.id, output.input_file())
+            .reply_to(msg);
+        if let Some(emoji) = job.meta.emojis.first() {
+            request = request.emoji(emoji);
+        }
+        request.send_retry().await
+    } else {
+        let mut request = bot
+            .send_document(msg.chat.id, output.input_file())
+            .disable_content_type_detection(true)
+            .reply_to(msg);
+        if let Some(caption) = output.caption() {
+            request = request.caption(caption);
+        }
+        request.send_retry().await
+    }
+}
+
+/// 下载、转换并以回复 `msg` 的形式发送结果，相同的文件和选项直接重发缓存的结果，重发失败时重新处理
+///
+/// `msg` 为触发处理的消息，用于限流、回复和日志。
+#[allow(clippy::too_many_arguments)]
+async fn process_and_reply(
+    bot: &Bot,
+    msg: &Message,
+    job: MediaJob,
+    auth: &SharedAuth,
+    limiter: &SharedLimiter,
+    history: &SharedHistory,
+    cache: &SharedCache,
+    encoder: &EncoderConfig,
+    lang: Lang,
+) -> anyhow::Result<()> {
+    let cache_key = job.cache_key(encoder);
+    let user_id = limited_user(msg, auth);
+    if let Some(cached) = cache.get(&cache_key) {
+        if reject_if_limited(bot, msg, user_id, 0, limiter, lang).await? {
+            return Ok(());
+        }
+        log::info!("ChatID: {}, 命中结果缓存: {}", msg.chat.id, cache_key);
+        let output = Output::Cached(cached);
+        match send_output(bot, msg, &job, &output).await {
+            Ok(sent) => {
+                record_sent(history, cache, msg, &job, &output, &sent, encoder).await?;
+                log::info!("ChatID: {}, 已重发缓存的结果", msg.chat.id);
+                return Ok(());
             }
-            Some(mime) => {
-                bot.send_message(
+            Err(e) => {
+                // 缓存的 file_id 失效时移除，改为重新处理
+                log::warn!(
+                    "ChatID: {}, 重发缓存的结果失败，重新处理: {}",
                     msg.chat.id,
-                    format!("不支持的文档MIME类型: {}。请发送图片或WebM视频。", mime),
+                    e
+                );
+                cache.remove(&cache_key).await?;
+            }
+        }
+    }
+
+    if reject_if_limited(bot, msg, user_id, job.file.size as u64, limiter, lang).await? {
+        return Ok(());
+    }
+    let output = match download_and_convert(
+        bot,
+        job.file.clone(),
+        job.target,
+        &job.options,
+        encoder,
+        lang,
+        user_id.map(|user_id| UserQuota { limiter, user_id }),
+    )
+    .await
+    {
+        Ok((converted, input_size)) => Output::Converted {
+            converted,
+            input_size,
+        },
+        Err(e) => {
+            bot.send_message(
+                msg.chat.id,
+                tr!(lang, ProcessingFailed, error_message(&e, lang)),
+            )
+            .reply_to(msg)
+            .send_retry()
+            .await?;
+            log::error!("文件处理失败: {:?}", e);
+            return Ok(());
+        }
+    };
+
+    let sent = send_output(bot, msg, &job, &output).await?;
+    record_sent(history, cache, msg, &job, &output, &sent, encoder).await?;
+    log::info!("ChatID: {}, 处理成功，已发送结果", msg.chat.id);
+    // 结果已经发送，记录用量失败时只写日志
+    if let (Some(user_id), Some((conversions, input_size))) = (user_id, output.usage())
+        && let Err(e) = limiter.record_batch(user_id, conversions, input_size).await
+    {
+        log::error!("记录用户 {} 的用量失败: {:?}", user_id, e);
+    }
+    Ok(())
+}
+
+/// 处理一个相册：按原始顺序转换每一项，以贴纸序列或文档相册返回，并发送一条汇总
+#[allow(clippy::too_many_arguments)]
+async fn process_album(
+    bot: Bot,
+    messages: Vec<Message>,
+    target: Target,
+    options: ProcessOptions,
+    auth: SharedAuth,
+    limiter: SharedLimiter,
+    history: SharedHistory,
+    cache: SharedCache,
+    encoder: EncoderConfig,
+    lang: Lang,
+) -> anyhow::Result<()> {
+    let Some(first) = messages.first() else {
+        return Ok(());
+    };
+    log::info!(
+        "ChatID: {}, 处理相册，共 {} 项，目标: {:?}",
+        first.chat.id,
+        messages.len(),
+        target
+    );
+
+    // 相册的说明文字通常只在第一项上，没有自己说明文字的项沿用它
+    let album_caption = messages.iter().find(|msg| msg.caption().is_some());
+
+    let jobs: Vec<Result<MediaJob, String>> = messages
+        .iter()
+        .map(|msg| {
+            let file = extract_media_file(msg, lang)
+                .and_then(|file| file.ok_or_else(|| tr!(lang, NoMedia)))?;
+            let source = match album_caption {
+                Some(captioned) if msg.caption().is_none() => captioned,
+                _ => msg,
+            };
+            MediaJob::new(file, target, source, options.clone(), lang)
+        })
+        .collect();
+
+    // 整个相册只消耗一次频率限制，但按未缓存项的数量和总大小检查配额
+    let uncached: Vec<&MediaJob> = jobs
+        .iter()
+        .flatten()
+        .filter(|job| cache.get(&job.cache_key(&encoder)).is_none())
+        .collect();
+    let total_bytes = uncached.iter().map(|job| job.file.size as u64).sum();
+    let user_id = limited_user(first, &auth);
+    if reject_if_limited_batch(
+        &bot,
+        first,
+        user_id,
+        uncached.len() as u32,
+        total_bytes,
+        &limiter,
+        lang,
+    )
+    .await?
+    {
+        return Ok(());
+    }
+
+    let mut results = Vec::with_capacity(jobs.len());
+    for job in jobs {
+        let result = match job {
+            Ok(job) => match cache.get(&job.cache_key(&encoder)) {
+                Some(cached) => Ok((Output::Cached(cached), job)),
+                None => download_and_convert(
+                    &bot,
+                    job.file.clone(),
+                    target,
+                    &job.options,
+                    &encoder,
+                    lang,
+                    user_id.map(|user_id| UserQuota {
+                        limiter: &limiter,
+                        user_id,
+                    }),
                 )
-                .await?;
-                return Ok(());
+                .await
+                .map(|(converted, input_size)| {
+                    let output = Output::Converted {
+                        converted,
+                        input_size,
+                    };
+                    (output, job)
+                })
+                .map_err(|e| {
+                    log::error!("相册项处理失败: {:?}", e);
+                    error_message(&e, lang)
+                }),
+            },
+            Err(e) => Err(e),
+        };
+        results.push(result);
+    }
+
+    // 按原始顺序发送：贴纸逐个发送，文档合并为相册（每组最多 10 个）
+    // 发送失败的项记入汇总，不影响其他项
+    let mut send_errors: HashMap<usize, String> = HashMap::new();
+    let mut documents = Vec::new();
+    let mut document_items = Vec::new();
+    for (index, result) in results.iter().enumerate() {
+        let Ok((output, job)) = result else {
+            continue;
+        };
+        if output.is_sticker() {
+            match send_output(&bot, first, job, output).await {
+                Ok(sent) => {
+                    if let Err(e) =
+                        record_sent(&history, &cache, first, job, output, &sent, &encoder).await
+                    {
+                        log::error!("保存相册项记录失败: {:?}", e);
+                    }
+                }
+                Err(e) => {
+                    log::error!("相册项发送失败: {:?}", e);
+                    send_errors.insert(index, e.to_string());
+                    continue;
+                }
             }
-            None => document.file.id.clone(),
-        }
-    } else if let Some(sticker) = msg.sticker() {
-        sticker.file.id.clone()
-    } else if let Some(animation) = msg.animation() {
-        match animation
-            .mime_type
-            .as_ref()
-            .map(|mime| mime.to_string())
-            .as_deref()
+        } else {
+            let mut document = InputMediaDocument::new(output.input_file());
+            document.disable_content_type_detection = Some(true);
+            document.caption = output.caption().cloned();
+            documents.push(InputMedia::Document(document));
+            document_items.push((index, output, job));
+        }
+        if let (Some(user_id), Some((conversions, input_size))) = (user_id, output.usage())
+            && let Err(e) = limiter.record_batch(user_id, conversions, input_size).await
         {
-            Some(mime) if mime.starts_with("video/") => animation.file.id.clone(),
-            Some(mime) => {
-                bot.send_message(
-                    msg.chat.id,
-                    format!("不支持的动画MIME类型: {}。请发送WebM视频。", mime),
-                )
-                .await?;
-                return Ok(());
+            log::error!("记录用户 {} 的用量失败: {:?}", user_id, e);
+        }
+    }
+    for (chunk, chunk_items) in documents.chunks(10).zip(document_items.chunks(10)) {
+        match bot
+            .send_media_group(first.chat.id, chunk.to_vec())
+            .reply_to(first)
+            .send_retry()
+            .await
+        {
+            Ok(sent) => {
+                for (sent, (_, output, job)) in sent.iter().zip(chunk_items) {
+                    if let Err(e) =
+                        record_sent(&history, &cache, first, job, output, sent, &encoder).await
+                    {
+                        log::error!("保存相册项记录失败: {:?}", e);
+                    }
+                }
+            }
+            Err(e) => {
+                log::error!("相册文档发送失败: {:?}", e);
+                for (index, _, _) in chunk_items {
+                    send_errors.insert(*index, e.to_string());
+                }
             }
-            None => animation.file.id.clone(),
         }
-    } else {
-        bot.send_message(msg.chat.id, "请发送图片或WebM视频")
+    }
+
+    // 汇总
+    let mut succeeded = 0;
+    let mut failures = String::new();
+    for (index, result) in results.iter().enumerate() {
+        match (result, send_errors.get(&index)) {
+            (Ok(_), None) => succeeded += 1,
+            (Ok(_), Some(e)) | (Err(e), _) => {
+                failures.push_str(&tr!(lang, AlbumItemFailed, index + 1, e));
+            }
+        }
+    }
+    let mut summary = tr!(lang, AlbumDone, succeeded, results.len());
+    summary.push_str(&failures);
+    bot.send_message(first.chat.id, summary)
+        .reply_to(first)
+        .send_retry()
+        .await?;
+    Ok(())
+}
+
+/// 从消息中提取媒体文件，无法处理时回复提示并返回 None
+async fn media_file_or_reply(
+    bot: &Bot,
+    msg: &Message,
+    source: &Message,
+    lang: Lang,
+) -> anyhow::Result<Option<FileMeta>> {
+    let text = match extract_media_file(source, lang) {
+        Ok(Some(file)) => return Ok(Some(file)),
+        Ok(None) => tr!(lang, SendMedia),
+        Err(text) => text,
+    };
+    bot.send_message(msg.chat.id, text)
+        .reply_to(msg)
+        .send_retry()
+        .await?;
+    Ok(None)
+}
+
+/// 提取命令所回复的消息中的媒体文件，未回复或无法处理时回复提示并返回 None
+pub async fn replied_media_or_reply<'a>(
+    bot: &Bot,
+    msg: &'a Message,
+    lang: Lang,
+) -> anyhow::Result<Option<(&'a Message, FileMeta)>> {
+    let Some(replied) = msg.reply_to_message() else {
+        bot.send_message(msg.chat.id, tr!(lang, ReplyToMedia))
+            .reply_to(msg)
+            .send_retry()
             .await?;
-        return Ok(());
+        return Ok(None);
     };
+    Ok(media_file_or_reply(bot, msg, replied, lang)
+        .await?
+        .map(|file| (replied, file)))
+}
 
-    // 下载文件
-    let tg_file = bot.get_file(file_id).await?;
-    let input_temp_file = tempfile::NamedTempFile::new().context("无法创建输入临时文件")?;
-    let input_file_path = input_temp_file.path().to_path_buf();
+/// 回复选项无效的提示
+async fn reply_invalid_options(bot: &Bot, msg: &Message, text: String) -> anyhow::Result<()> {
+    bot.send_message(msg.chat.id, text)
+        .reply_to(msg)
+        .send_retry()
+        .await?;
+    Ok(())
+}
 
-    let file_url = format!(
-        "https://api.telegram.org/file/bot{}/{}",
-        bot.token(),
-        tg_file.path
+/// 对被回复的消息中的媒体执行处理（/sticker、/gif、/emoji）
+#[allow(clippy::too_many_arguments)]
+async fn process_replied(
+    bot: &Bot,
+    msg: &Message,
+    target: Target,
+    options: ProcessOptions,
+    auth: &SharedAuth,
+    limiter: &SharedLimiter,
+    history: &SharedHistory,
+    cache: &SharedCache,
+    encoder: &EncoderConfig,
+    lang: Lang,
+) -> anyhow::Result<()> {
+    let Some((replied, file)) = replied_media_or_reply(bot, msg, lang).await? else {
+        return Ok(());
+    };
+    log::info!(
+        "ChatID: {}, 处理被回复的消息 {}，目标: {:?}",
+        msg.chat.id,
+        replied.id,
+        target
     );
-    let bytes = reqwest::get(&file_url).await?.bytes().await?;
-    tokio_fs::write(&input_file_path, &bytes)
-        .await
-        .context("无法写入输入临时文件")?;
-
-    // 检测文件类型
-    let detected_type_result =
-        infer::get_from_path(&input_file_path).context("无法从路径获取类型信息推断")?;
-    let (is_image, is_video, detected_mime_str) = match detected_type_result {
-        Some(info) => (
-            info.mime_type().starts_with("image/"),
-            info.mime_type().starts_with("video/"),
-            info.mime_type().to_string(),
-        ),
-        None => (false, false, "未知 (infer无法识别)".to_string()),
+    let job = match MediaJob::new(file, target, replied, options, lang) {
+        Ok(job) => job,
+        Err(text) => return reply_invalid_options(bot, msg, text).await,
     };
+    process_and_reply(bot, msg, job, auth, limiter, history, cache, encoder, lang).await
+}
 
-    // 获取当前模式
-    let current_mode = get_chat_mode(&mode_state, msg.chat.id);
-    log::info!("ChatID: {}, 当前模式: {:?}", msg.chat.id, current_mode);
+/// /search 每次最多返回的结果数
+const SEARCH_LIMIT: usize = 5;
 
-    // GIF 模式下直接发送图片作为文档
-    if current_mode == Mode::GifDownload && is_image {
-        let input_doc = teloxide::types::InputFile::file(&input_file_path);
-        bot.send_document(msg.chat.id, input_doc)
-            .disable_content_type_detection(true)
+/// 按表情或关键词搜索用户处理过的结果并重新发送（/search）
+async fn send_search_results(
+    bot: &Bot,
+    msg: &Message,
+    query: &str,
+    history: &SharedHistory,
+    lang: Lang,
+) -> anyhow::Result<()> {
+    let Some(user) = msg.from.as_ref() else {
+        return Ok(());
+    };
+    if query.trim().is_empty() {
+        bot.send_message(msg.chat.id, tr!(lang, SearchUsage))
+            .reply_to(msg)
+            .send_retry()
             .await?;
         return Ok(());
     }
 
-    // GIF 模式下不支持非视频文件
-    if current_mode == Mode::GifDownload && !is_video {
-        bot.send_message(
-            msg.chat.id,
-            format!(
-                "不支持的文件类型 (检测为: {})。请发送视频、动图或动态贴纸。",
-                detected_mime_str
-            ),
-        )
-        .await?;
+    let entries = history.search(user.id, query, 0, SEARCH_LIMIT);
+    if entries.is_empty() {
+        bot.send_message(msg.chat.id, tr!(lang, SearchNoResults, query.trim()))
+            .reply_to(msg)
+            .send_retry()
+            .await?;
         return Ok(());
     }
-
-    // 处理输出
-    let processing_outcome: anyhow::Result<((tempfile::NamedTempFile, std::path::PathBuf), bool)>;
-
-    if is_image {
-        let output_temp = Builder::new()
-            .suffix(".webp")
-            .tempfile()
-            .context("无法创建WebP输出临时文件")?;
-        let output_path = output_temp.path().to_path_buf();
-        log::debug!(
-            "ChatID: {}, 输入: {:?}, 检测到的类型: {}. 输出到: {:?}",
-            msg.chat.id,
-            input_file_path,
-            detected_mime_str,
-            output_path
-        );
-        processing_outcome = process_image(&input_file_path, &output_path)
-            .await
-            .map(|_| ((output_temp, output_path), true))
-            .context("图片处理失败");
-    } else if is_video {
-        if current_mode == Mode::StickerOptimize {
-            let output_temp = Builder::new()
-                .suffix(".webm")
-                .tempfile()
-                .context("无法创建WebM输出临时文件")?;
-            let output_path = output_temp.path().to_path_buf();
-            log::debug!(
-                "ChatID: {}, 输入: {:?}, 检测到的类型: {}. 输出到: {:?}",
-                msg.chat.id,
-                input_file_path,
-                detected_mime_str,
-                output_path
-            );
-            processing_outcome = process_webm(&input_file_path, &output_path)
-                .await
-                .map(|_| ((output_temp, output_path), true))
-                .context("视频处理失败");
+    for entry in entries {
+        let file = InputFile::file_id(entry.file_id.into());
+        if entry.is_sticker {
+            bot.send_sticker(msg.chat.id, file)
+                .reply_to(msg)
+                .send_retry()
+                .await?;
         } else {
-            // GIF 模式
-            let output_temp = Builder::new()
-                .suffix(".gif")
-                .tempfile()
-                .context("无法创建GIF输出临时文件")?;
-            let output_path = output_temp.path().to_path_buf();
-            log::debug!(
-                "ChatID: {}, 输入: {:?}, 检测到的类型: {}. 输出GIF到: {:?}",
-                msg.chat.id,
-                input_file_path,
-                detected_mime_str,
-                output_path
-            );
-            processing_outcome = process_video_to_gif(&input_file_path, &output_path)
-                .await
-                .map(|_| ((output_temp, output_path), false))
-                .context("GIF转换失败");
+            bot.send_document(msg.chat.id, file)
+                .reply_to(msg)
+                .send_retry()
+                .await?;
         }
-    } else {
-        processing_outcome = Err(anyhow::anyhow!(
-            "不支持的文件类型 (检测为: {}). 请发送图片或WebM视频.",
-            detected_mime_str
+    }
+    Ok(())
+}
+
+/// 显示被回复消息中媒体的信息（/info）
+async fn send_media_info(bot: &Bot, msg: &Message, lang: Lang) -> anyhow::Result<()> {
+    let Some((replied, file)) = replied_media_or_reply(bot, msg, lang).await? else {
+        return Ok(());
+    };
+
+    let mut lines = vec![
+        tr!(lang, InfoTitle),
+        format!("- file_unique_id: {}", file.unique_id),
+        tr!(lang, InfoSize, format!("{:.1}", file.size as f64 / 1024.0)),
+    ];
+    if let Some(sticker) = replied.sticker() {
+        let kind = if sticker.is_video() {
+            Msg::InfoVideoSticker
+        } else if sticker.is_animated() {
+            Msg::InfoAnimatedSticker
+        } else {
+            Msg::InfoStaticSticker
+        };
+        lines.push(tr!(
+            lang,
+            InfoSticker,
+            sticker.width,
+            sticker.height,
+            kind.text(lang)
         ));
+        if let Some(emoji) = &sticker.emoji {
+            lines.push(tr!(lang, InfoEmoji, emoji));
+        }
+        if let Some(set_name) = &sticker.set_name {
+            lines.push(tr!(lang, InfoSet, set_name));
+        }
     }
 
-    // 处理结果
-    match processing_outcome {
-        Ok(((_output_temp_file_guard, processed_file_path), is_sticker)) => {
-            let input_doc = teloxide::types::InputFile::file(&processed_file_path);
-            if is_sticker {
-                bot.send_sticker(msg.chat.id, input_doc).await?;
-            } else {
-                bot.send_document(msg.chat.id, input_doc)
-                    .disable_content_type_detection(true)
-                    .await?;
+    let (input_temp_file, _) = download_file(bot, file.id).await?;
+    let detected = detect_type(input_temp_file.path())?;
+    lines.push(tr!(lang, InfoMime, detected.mime));
+    if detected.is_image || detected.is_video {
+        match media_dimensions(input_temp_file.path(), &detected) {
+            Ok((width, height)) => lines.push(tr!(lang, InfoDimensions, width, height)),
+            Err(e) => {
+                log::warn!("读取尺寸失败: {:?}", e);
+                lines.push(tr!(lang, InfoNoDimensions, error_message(&e, lang)));
             }
-            log::info!(
-                "ChatID: {}, 处理成功，发送文件: {:?}",
-                msg.chat.id,
-                processed_file_path
-            );
-        }
-        Err(e) => {
-            bot.send_message(msg.chat.id, format!("处理失败: {}", e.root_cause()))
-                .await?;
-            log::error!("文件处理失败: {:?}", e);
         }
     }
+    if detected.is_video
+        && let Ok(info) = probe_video(input_temp_file.path())
+    {
+        lines.push(tr!(lang, InfoFps, format!("{:.2}", info.fps)));
+        lines.push(tr!(lang, InfoDuration, format!("{:.2}", info.duration)));
+    }
 
+    bot.send_message(msg.chat.id, lines.join("\n"))
+        .reply_to(msg)
+        .send_retry()
+        .await?;
     Ok(())
 }
+
+#[allow(clippy::too_many_arguments)]
+pub async fn handle_file(
+    bot: Bot,
+    msg: Message,
+    mode_state: ModeState,
+    auth: SharedAuth,
+    limiter: SharedLimiter,
+    history: SharedHistory,
+    cache: SharedCache,
+    albums: SharedAlbums,
+    encoder: EncoderConfig,
+    lang: Lang,
+) -> anyhow::Result<()> {
+    log::info!("ChatID: {}, Received New message", msg.chat.id);
+
+    // 获取当前模式
+    let chat_id = msg.chat.id;
+    let current_mode = get_chat_mode(&mode_state, chat_id);
+    log::info!("ChatID: {}, 当前模式: {:?}", chat_id, current_mode);
+
+    // 相册消息先收集，由第一条消息负责在收集完成后统一处理
+    if let Some(group_id) = msg.media_group_id().cloned() {
+        if albums.push(group_id.clone(), msg) {
+            let options = get_chat_options(&mode_state, chat_id);
+            tokio::spawn(async move {
+                let messages = albums.collect(&group_id).await;
+                if let Err(e) = process_album(
+                    bot,
+                    messages,
+                    current_mode.into(),
+                    options,
+                    auth,
+                    limiter,
+                    history,
+                    cache,
+                    encoder,
+                    lang,
+                )
+                .await
+                {
+                    log::error!("相册处理失败: {:?}", e);
+                }
+            });
+        }
+        return Ok(());
+    }
+
+    let Some(file) = media_file_or_reply(&bot, &msg, &msg, lang).await? else {
+        return Ok(());
+    };
+
+    let options = get_chat_options(&mode_state, msg.chat.id);
+    let job = match MediaJob::new(file, current_mode.into(), &msg, options, lang) {
+        Ok(job) => job,
+        Err(text) => return reply_invalid_options(&bot, &msg, text).await,
+    };
+    process_and_reply(
+        &bot, &msg, job, &auth, &limiter, &history, &cache, &encoder, lang,
+    )
+    .await
+}
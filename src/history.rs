@@ -0,0 +1,117 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use teloxide::types::{ChatId, UserId};
+
+use crate::media::Target;
+use crate::metadata::StickerMeta;
+use crate::storage::{JsonWriter, load_json, to_json};
+
+const HISTORY_FILE: &str = "history.json";
+/// 每个用户最多保留的处理记录数，超出后丢弃最旧的记录
+const MAX_ENTRIES_PER_USER: usize = 500;
+
+/// 一次成功的处理记录
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    /// 处理时间（Unix 时间戳，秒）
+    pub time: u64,
+    pub chat_id: ChatId,
+    /// 输入文件的 file_unique_id
+    pub input_unique_id: String,
+    pub target: Target,
+    /// 发送结果的 file_id，可直接再次发送
+    pub file_id: String,
+    /// 结果是否为贴纸
+    pub is_sticker: bool,
+    #[serde(flatten)]
+    pub meta: StickerMeta,
+}
+
+impl HistoryEntry {
+    pub fn new(
+        chat_id: ChatId,
+        input_unique_id: String,
+        target: Target,
+        file_id: String,
+        is_sticker: bool,
+        meta: StickerMeta,
+    ) -> Self {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0);
+        Self {
+            time,
+            chat_id,
+            input_unique_id,
+            target,
+            file_id,
+            is_sticker,
+            meta,
+        }
+    }
+}
+
+/// 每个用户的处理历史，持久化到数据目录
+pub struct HistoryStore {
+    entries: Mutex<HashMap<UserId, Vec<HistoryEntry>>>,
+    writer: JsonWriter,
+}
+
+pub type SharedHistory = Arc<HistoryStore>;
+
+impl HistoryStore {
+    /// 从数据目录加载处理历史
+    pub fn load() -> Result<Self> {
+        let entries = load_json(HISTORY_FILE)?.unwrap_or_default();
+        Ok(Self {
+            entries: Mutex::new(entries),
+            writer: JsonWriter::new(HISTORY_FILE),
+        })
+    }
+
+    /// 记录一次处理结果
+    pub async fn record(&self, user_id: UserId, entry: HistoryEntry) -> Result<()> {
+        {
+            let mut entries = self.entries.lock().unwrap();
+            let user_entries = entries.entry(user_id).or_default();
+            user_entries.push(entry);
+            if user_entries.len() > MAX_ENTRIES_PER_USER {
+                let excess = user_entries.len() - MAX_ENTRIES_PER_USER;
+                user_entries.drain(..excess);
+            }
+        }
+        self.writer
+            .save(|| to_json(&*self.entries.lock().unwrap()))
+            .await
+    }
+
+    /// 按表情或关键词搜索用户的处理结果，最新的在前，跳过前 `offset` 个结果
+    pub fn search(
+        &self,
+        user_id: UserId,
+        query: &str,
+        offset: usize,
+        limit: usize,
+    ) -> Vec<HistoryEntry> {
+        self.entries
+            .lock()
+            .unwrap()
+            .get(&user_id)
+            .map(|entries| {
+                entries
+                    .iter()
+                    .rev()
+                    .filter(|entry| entry.meta.matches(query))
+                    .skip(offset)
+                    .take(limit)
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,534 @@
+use std::fmt::Display;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use teloxide::types::{Message, User};
+
+use crate::state::LangStore;
+
+/// 界面语言
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Lang {
+    #[default]
+    Zh,
+    En,
+}
+
+impl Lang {
+    /// 所有支持的语言
+    pub const ALL: [Lang; 2] = [Lang::Zh, Lang::En];
+
+    /// Telegram 的 IETF 语言代码
+    pub fn code(self) -> &'static str {
+        match self {
+            Lang::Zh => "zh",
+            Lang::En => "en",
+        }
+    }
+
+    /// 根据 Telegram 客户端的 language_code 选择语言：中文客户端使用中文，其他语言使用英文，未知时使用中文
+    pub fn from_code(code: Option<&str>) -> Self {
+        match code {
+            Some(code) if !code.to_lowercase().starts_with("zh") => Lang::En,
+            _ => Lang::Zh,
+        }
+    }
+
+    /// 用户的语言：优先使用 /lang 设置，否则根据客户端语言选择
+    pub fn of_user(user: &User, langs: &LangStore) -> Self {
+        langs
+            .get(user.id)
+            .unwrap_or_else(|| Self::from_code(user.language_code.as_deref()))
+    }
+
+    /// 消息发送者的语言
+    pub fn of(msg: &Message, langs: &LangStore) -> Self {
+        msg.from
+            .as_ref()
+            .map(|user| Self::of_user(user, langs))
+            .unwrap_or_default()
+    }
+}
+
+impl FromStr for Lang {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "zh" | "zh-cn" | "zh-hans" | "cn" | "中文" => Ok(Lang::Zh),
+            "en" | "en-us" | "english" => Ok(Lang::En),
+            _ => Err(()),
+        }
+    }
+}
+
+/// 依次用参数替换模板中的 `{}`
+pub fn fill(template: &str, args: &[&dyn Display]) -> String {
+    let mut result = String::with_capacity(template.len());
+    let mut args = args.iter();
+    let mut parts = template.split("{}");
+    if let Some(first) = parts.next() {
+        result.push_str(first);
+    }
+    for part in parts {
+        if let Some(arg) = args.next() {
+            result.push_str(&arg.to_string());
+        }
+        result.push_str(part);
+    }
+    result
+}
+
+/// 翻译消息目录中的文本：`tr!(lang, Key)` 或 `tr!(lang, Key, 参数...)`
+#[macro_export]
+macro_rules! tr {
+    ($lang:expr, $key:ident) => {
+        $crate::i18n::Msg::$key.text($lang).to_string()
+    };
+    ($lang:expr, $key:ident, $($arg:expr),+ $(,)?) => {{
+        // 参数引用不能跨越 await 存活，在块内生成字符串
+        let text = $crate::i18n::fill(
+            $crate::i18n::Msg::$key.text($lang),
+            &[$(&$arg as &dyn std::fmt::Display),+],
+        );
+        text
+    }};
+}
+
+macro_rules! catalog {
+    ($($key:ident => ($zh:expr, $en:expr),)*) => {
+        /// 消息目录中的条目
+        #[derive(Clone, Copy, Debug)]
+        pub enum Msg {
+            $($key,)*
+        }
+
+        impl Msg {
+            /// 该条目在指定语言下的文本，`{}` 为参数占位符
+            pub fn text(self, lang: Lang) -> &'static str {
+                match (self, lang) {
+                    $(
+                        (Msg::$key, Lang::Zh) => $zh,
+                        (Msg::$key, Lang::En) => $en,
+                    )*
+                }
+            }
+        }
+    };
+}
+
+catalog! {
+    // 模式
+    ModeStickerOptimize => ("贴纸优化模式", "Sticker Optimize Mode"),
+    ModeGifDownload => ("GIF下载模式", "GIF Download Mode"),
+    WelcomeStickerOptimize => (
+        "📦 **贴纸优化模式**\n\
+        将图片和视频转为 Telegram 贴纸格式\n\
+        - 图片 → WebP 贴纸\n\
+        - 视频 → VP9 WebM 贴纸",
+        "📦 **Sticker Optimize Mode**\n\
+        Converts images and videos into Telegram sticker format\n\
+        - Image → WebP sticker\n\
+        - Video → VP9 WebM sticker"
+    ),
+    WelcomeGifDownload => (
+        "🎞️ **GIF 下载模式**\n\
+        将视频转为 GIF 文件返回\n\
+        - 视频 → GIF\n\
+        - 动态贴纸 → GIF\n\
+        - 动图 → GIF\n\
+        - 图片 → 作为文档发送",
+        "🎞️ **GIF Download Mode**\n\
+        Converts videos into GIF files\n\
+        - Video → GIF\n\
+        - Video sticker → GIF\n\
+        - Animation → GIF\n\
+        - Image → sent as a document"
+    ),
+    Welcome => (
+        "欢迎使用 Telegram Sticker 工具！\n\n\
+        **当前模式**: {}\n\n\
+        {}\n\n\
+        转发文字消息给我可以生成语录贴纸。\n\
+        使用 /mode 切换工作模式。",
+        "Welcome to the Telegram Sticker tool!\n\n\
+        **Current mode**: {}\n\n\
+        {}\n\n\
+        Forward text messages to me to turn them into a quote sticker.\n\
+        Use /mode to switch modes."
+    ),
+    ModeSwitched => ("✅ 已切换到 **{}**\n\n{}", "✅ Switched to **{}**\n\n{}"),
+    ModeSwitchedSticker => (
+        "现在可以发送图片或视频，我将处理成贴纸格式。",
+        "Send me images or videos and I will turn them into stickers."
+    ),
+    ModeSwitchedGif => (
+        "现在可以发送视频、动图、动态贴纸或图片，我将返回 GIF 文件或原图。",
+        "Send me videos, animations, video stickers or images and I will return GIF files or the original image."
+    ),
+
+    // 语言
+    LangCurrent => (
+        "当前语言: 中文\n使用 /lang en 切换为英文，/lang auto 跟随客户端语言。",
+        "Current language: English\nUse /lang zh to switch to Chinese, /lang auto to follow your client language."
+    ),
+    LangSwitched => ("✅ 已切换为中文", "✅ Switched to English"),
+    LangAuto => ("✅ 语言将跟随客户端设置", "✅ Language now follows your client settings"),
+    LangUnknown => ("不支持的语言: {}。可选: zh、en、auto", "Unsupported language: {}. Options: zh, en, auto"),
+
+    // 用量
+    QuotaUnlimited => ("{}{} / 不限", "{}{} / unlimited"),
+    Quota => (
+        "📊 今日用量\n\
+        - 转换次数: {}\n\
+        - 文件大小: {}\n\
+        - 频率限制: 每分钟 {} 次，突发 {} 次",
+        "📊 Today's usage\n\
+        - Conversions: {}\n\
+        - File size: {}\n\
+        - Rate limit: {} per minute, burst of {}"
+    ),
+    RateLimited => ("请求过于频繁，请在 {} 秒后重试。", "Too many requests, please retry in {} seconds."),
+    DailyConversionsExceeded => (
+        "今日转换次数已达上限 ({} 次)，请明天再试。",
+        "Daily conversion limit reached ({}), please try again tomorrow."
+    ),
+    DailyBytesExceeded => (
+        "今日处理的文件总大小已达上限 ({}MB)，请明天再试。",
+        "Daily file size limit reached ({}MB), please try again tomorrow."
+    ),
+
+    // 访问控制
+    Unauthorized => ("抱歉，您未被授权使用此机器人。", "Sorry, you are not authorized to use this bot."),
+    Empty => ("无", "none"),
+    RuleChat => ("聊天 {}", "chat {}"),
+    RuleUser => ("用户 {}", "user {}"),
+    RuleMemberOf => ("群组 {} 的成员", "members of group {}"),
+    InvalidRule => (
+        "无效的规则: '{}'。格式: <id>、chat:<id>、user:<id> 或 member:<群组id>",
+        "Invalid rule: '{}'. Format: <id>, chat:<id>, user:<id> or member:<group id>"
+    ),
+    RuleAllowed => ("✅ 已将{}加入白名单", "✅ Added {} to the whitelist"),
+    RuleAlreadyAllowed => ("{}已在白名单中", "{} is already whitelisted"),
+    RuleDenied => ("✅ 已将{}移出白名单", "✅ Removed {} from the whitelist"),
+    RuleNotAllowed => ("{}不在白名单中", "{} is not whitelisted"),
+    RuleBlocked => ("✅ 已将{}加入黑名单", "✅ Added {} to the deny-list"),
+    RuleAlreadyBlocked => ("{}已在黑名单中", "{} is already on the deny-list"),
+    RuleUnblocked => ("✅ 已将{}移出黑名单", "✅ Removed {} from the deny-list"),
+    RuleNotBlocked => ("{}不在黑名单中", "{} is not on the deny-list"),
+    WhitelistEnabled => ("已启用", "enabled"),
+    WhitelistDisabled => ("未启用（响应黑名单以外的所有用户）", "disabled (everyone not on the deny-list is allowed)"),
+    AccessRules => (
+        "白名单: {}\n\
+        - 聊天: {}\n\
+        - 用户: {}\n\
+        - 群组成员: {}\n\
+        黑名单\n\
+        - 聊天: {}\n\
+        - 用户: {}",
+        "Whitelist: {}\n\
+        - Chats: {}\n\
+        - Users: {}\n\
+        - Group members: {}\n\
+        Deny-list\n\
+        - Chats: {}\n\
+        - Users: {}"
+    ),
+
+    // 处理
+    ProcessingFailed => ("处理失败: {}", "Processing failed: {}"),
+    NoMedia => ("消息中没有媒体", "The message contains no media"),
+    SendMedia => ("请发送图片或WebM视频", "Please send an image or a WebM video"),
+    UnsupportedDocument => (
+        "不支持的文档MIME类型: {}。请发送图片、WebM视频或ZIP压缩包。",
+        "Unsupported document MIME type: {}. Please send an image, a WebM video or a ZIP archive."
+    ),
+    UnsupportedAnimation => (
+        "不支持的动画MIME类型: {}。请发送WebM视频。",
+        "Unsupported animation MIME type: {}. Please send a WebM video."
+    ),
+    ReplyToMedia => (
+        "请回复一条包含图片、视频或贴纸的消息来使用此命令。",
+        "Reply to a message containing an image, video or sticker to use this command."
+    ),
+    AlbumDone => ("📚 相册处理完成：成功 {}/{}", "📚 Album processed: {}/{} succeeded"),
+    AlbumItemFailed => ("\n- 第 {} 项失败: {}", "\n- Item {} failed: {}"),
+    ArchiveDone => (
+        "📦 压缩包处理完成：成功 {}，失败 {}。详情见 manifest.json",
+        "📦 Archive processed: {} succeeded, {} failed. See manifest.json for details"
+    ),
+    GridDone => (
+        "🧩 已切分为 {}x{} 个自定义表情，按文件名顺序逐行添加即可拼出完整图片",
+        "🧩 Split into {}x{} custom emoji. Add them row by row in file name order to rebuild the full image"
+    ),
+    UnsupportedForGif => (
+        "不支持的文件类型 (检测为: {})。请发送视频、动图或动态贴纸。",
+        "Unsupported file type (detected: {}). Please send a video, an animation or an animated sticker."
+    ),
+    UnsupportedForSticker => (
+        "不支持的文件类型 (检测为: {})。请发送图片或WebM视频。",
+        "Unsupported file type (detected: {}). Please send an image or a WebM video."
+    ),
+    ImageTooLarge => (
+        "图片太大 ({}KB)，即使压缩后仍超过{}KB限制",
+        "The image is too large ({}KB) and still exceeds the {}KB limit after compression"
+    ),
+    VideoTooLarge => (
+        "视频太大 ({}KB)，即使压缩后仍超过{}KB限制",
+        "The video is too large ({}KB) and still exceeds the {}KB limit after compression"
+    ),
+    GifTooLarge => (
+        "GIF文件太大 ({}MB)，超过{}MB限制",
+        "The GIF is too large ({}MB), over the {}MB limit"
+    ),
+    ImageDecodeFailed => (
+        "无法解码图片，文件可能已损坏或格式不受支持",
+        "Could not decode the image, the file may be corrupted or in an unsupported format"
+    ),
+    VideoProbeFailed => (
+        "无法读取视频信息，文件可能已损坏或格式不受支持",
+        "Could not read the video info, the file may be corrupted or in an unsupported format"
+    ),
+    VideoDecodeFailed => ("视频解码失败", "Failed to decode the video"),
+    VideoEncodeFailed => ("视频编码失败", "Failed to encode the video"),
+    GifEncodeFailed => ("GIF生成失败", "Failed to generate the GIF"),
+    TgsRenderFailed => ("TGS动画渲染失败", "Failed to render the TGS animation"),
+    FontMissing => (
+        "服务器缺少绘制文字所需的字体",
+        "The server is missing the font needed to draw text"
+    ),
+    QuoteNoText => (
+        "没有可以生成语录的文字消息",
+        "There are no text messages to make a quote from"
+    ),
+    InternalError => (
+        "处理时发生内部错误，请稍后重试",
+        "An internal error occurred while processing, please try again later"
+    ),
+    ArchiveTooManyFiles => (
+        "压缩包中的文件过多，最多支持 {} 个",
+        "Too many files in the archive, at most {} are supported"
+    ),
+    ArchiveTooLarge => (
+        "压缩包解压后过大，最多支持 {}MB",
+        "The archive is too large when extracted, at most {}MB is supported"
+    ),
+    ArchiveEmpty => ("压缩包中没有可处理的文件", "The archive contains no files to process"),
+    ArchiveNested => ("不支持嵌套的ZIP压缩包", "Nested ZIP archives are not supported"),
+    ResultArchiveTooLarge => (
+        "结果压缩包太大 ({}MB)，超过50MB限制",
+        "The resulting archive is too large ({}MB), over the 50MB limit"
+    ),
+
+    // 处理选项
+    InvalidOption => (
+        "无效的选项: {}。例如: bg:auto、bg:#ffffff、tolerance:60、feather:2、trim:on、trim:16、outline:8、outlinecolor:#000000、shadow:on、crop:1:1、rotate:90、flip:h、square:on、grid:3x3、text: 你好 / bottom",
+        "Invalid option: {}. Examples: bg:auto, bg:#ffffff, tolerance:60, feather:2, trim:on, trim:16, outline:8, outlinecolor:#000000, shadow:on, crop:1:1, rotate:90, flip:h, square:on, grid:3x3, text: hello / bottom"
+    ),
+    RemoveBgUsage => (
+        "本聊天未开启背景去除。用法: /removebg <auto|#RRGGBB|off>，也可以在说明文字中写 bg:auto",
+        "Background removal is off in this chat. Usage: /removebg <auto|#RRGGBB|off>, or put bg:auto in the caption"
+    ),
+    RemoveBgStatus => (
+        "本聊天的背景去除: {}。使用 /removebg off 关闭",
+        "Background removal in this chat: {}. Use /removebg off to turn it off"
+    ),
+    RemoveBgEnabled => (
+        "✅ 已开启背景去除 ({})，之后的静态图片贴纸将带有透明背景",
+        "✅ Background removal enabled ({}); static stickers will now have a transparent background"
+    ),
+    RemoveBgDisabled => ("✅ 已关闭背景去除", "✅ Background removal disabled"),
+
+    // 搜索
+    SearchUsage => (
+        "用法: /search <表情或关键词>。处理媒体时在说明文字中写上表情和 #关键词 即可被搜索到。",
+        "Usage: /search <emoji or keyword>. Put emojis and #keywords in the caption when converting media to make it searchable."
+    ),
+    SearchNoResults => ("没有找到与「{}」匹配的结果", "No results matching \"{}\""),
+
+    // 媒体信息
+    InfoTitle => ("ℹ️ 文件信息", "ℹ️ File info"),
+    InfoSize => ("- 大小: {}KB", "- Size: {}KB"),
+    InfoSticker => ("- 贴纸: {}x{}, {}", "- Sticker: {}x{}, {}"),
+    InfoVideoSticker => ("视频贴纸", "video sticker"),
+    InfoAnimatedSticker => ("动态贴纸 (TGS)", "animated sticker (TGS)"),
+    InfoStaticSticker => ("静态贴纸", "static sticker"),
+    InfoEmoji => ("- 表情: {}", "- Emoji: {}"),
+    InfoSet => ("- 贴纸包: {}", "- Sticker set: {}"),
+    InfoMime => ("- 检测类型: {}", "- Detected type: {}"),
+    InfoDimensions => ("- 尺寸: {}x{}", "- Dimensions: {}x{}"),
+    InfoNoDimensions => ("- 无法读取尺寸: {}", "- Unable to read dimensions: {}"),
+    InfoFps => ("- 帧率: {}fps", "- Frame rate: {}fps"),
+    InfoDuration => ("- 时长: {}秒", "- Duration: {}s"),
+
+    // 贴纸包
+    PackInvalidName => (
+        "无效的贴纸包名称: '{}'。名称只能包含英文字母、数字和单个下划线，以字母开头，且不超过 {} 个字符。",
+        "Invalid sticker set name: '{}'. Names may only contain letters, digits and single underscores, must start with a letter and be at most {} characters."
+    ),
+    PackOperationFailed => ("操作失败: {}", "Operation failed: {}"),
+    NewPackUsage => (
+        "用法: 回复一条媒体消息并发送 /newpack <名称> <标题>",
+        "Usage: reply to a media message with /newpack <name> <title>"
+    ),
+    PackTitleTooLong => ("标题不能超过 64 个字符", "The title must be at most 64 characters"),
+    PackCreated => ("✅ 已创建贴纸包「{}」\n{}", "✅ Created sticker set \"{}\"\n{}"),
+    PackNotFoundCreate => (
+        "找不到该贴纸包。请先使用 /newpack 创建，或使用 /packs 查看你的贴纸包。",
+        "Sticker set not found. Create one with /newpack first, or use /packs to list your sets."
+    ),
+    PackNotFound => (
+        "找不到该贴纸包。请使用 /packs 查看你的贴纸包。",
+        "Sticker set not found. Use /packs to list your sets."
+    ),
+    StickerAdded => ("✅ 已添加到贴纸包「{}」\n{}", "✅ Added to sticker set \"{}\"\n{}"),
+    ReplyToPackSticker => (
+        "请回复贴纸包中的一个贴纸来使用此命令。",
+        "Reply to a sticker from the set to use this command."
+    ),
+    PackNotOwned => (
+        "只能修改你通过本机器人创建的贴纸包。",
+        "You can only modify sticker sets you created with this bot."
+    ),
+    StickerRemoved => ("✅ 已从贴纸包删除\n{}", "✅ Removed from the sticker set\n{}"),
+    MoveStickerUsage => (
+        "用法: 回复贴纸包中的贴纸并发送 /movesticker <位置>，位置从 1 开始",
+        "Usage: reply to a sticker from the set with /movesticker <position>, starting at 1"
+    ),
+    StickerMoved => ("✅ 已移动到第 {} 位\n{}", "✅ Moved to position {}\n{}"),
+    AddGridUsage => (
+        "用法: 回复一条媒体消息并发送 /addgrid <列x行> [名称] [标题]，例如 /addgrid 3x3 mypack 我的表情。每边最多 {} 格；表情包不存在时需要给出标题以新建。",
+        "Usage: reply to a media message with /addgrid <columns>x<rows> [name] [title], e.g. /addgrid 3x3 mypack My Emoji. At most {} per side; a title is required to create a new emoji set."
+    ),
+    NotEmojiPack => (
+        "「{}」不是自定义表情包，表情网格只能添加到自定义表情包。",
+        "\"{}\" is not a custom emoji set; emoji grids can only be added to custom emoji sets."
+    ),
+    GridAdded => (
+        "✅ 已将 {}x{} 的表情网格添加到「{}」\n{}",
+        "✅ Added a {}x{} emoji grid to \"{}\"\n{}"
+    ),
+    PackIconSet => ("✅ 已设置贴纸包「{}」的图标", "✅ Set the icon of sticker set \"{}\""),
+    EmojiPackIconUsage => (
+        "自定义表情包「{}」的图标只能是包中的表情，请回复包中的一个表情并发送 /setpackicon。",
+        "The icon of custom emoji set \"{}\" must be one of its emoji. Reply to an emoji from the set with /setpackicon."
+    ),
+    NoPacks => (
+        "你还没有通过本机器人创建贴纸包。回复一条媒体消息并发送 /newpack <名称> <标题> 来创建。",
+        "You have not created any sticker sets with this bot yet. Reply to a media message with /newpack <name> <title> to create one."
+    ),
+    PackList => ("你的贴纸包:\n{}", "Your sticker sets:\n{}"),
+    ClonePackUsage => (
+        "用法: /clonepack <新名称> <源贴纸包名称或链接> [更多源贴纸包...]",
+        "Usage: /clonepack <new name> <source set name or link> [more source sets...]"
+    ),
+    SetNotFound => ("找不到贴纸包: {}", "Sticker set not found: {}"),
+    CloneProgress => ("⏳ 正在复制贴纸包「{}」({}/{})", "⏳ Copying sticker set \"{}\" ({}/{})"),
+    CloneDone => (
+        "✅ 已复制贴纸包「{}」：成功 {}，失败 {}\n{}",
+        "✅ Copied sticker set \"{}\": {} succeeded, {} failed\n{}"
+    ),
+    CloneSkipped => (
+        "\n超出 {} 个贴纸的上限，已跳过 {} 个",
+        "\nOver the limit of {} stickers, skipped {}"
+    ),
+    CloneNoKeywords => (
+        "\nBot API 不提供贴纸的搜索关键词，复制的贴纸没有关键词",
+        "\nThe Bot API does not expose sticker search keywords, so the copies have none"
+    ),
+    InvalidSetNames => ("无法识别的贴纸包名称: {}", "Unrecognized sticker set names: {}"),
+    CloneEmpty => ("源贴纸包为空", "The source sets are empty"),
+    CloneFailed => ("复制失败: {}", "Copy failed: {}"),
+    ExportPackUsage => (
+        "用法: 回复一个贴纸并发送 /exportpack，或发送 /exportpack <贴纸包名称或链接>",
+        "Usage: reply to a sticker with /exportpack, or send /exportpack <set name or link>"
+    ),
+    ExportProgress => ("⏳ 正在导出贴纸包「{}」({}/{})", "⏳ Exporting sticker set \"{}\" ({}/{})"),
+    ExportFailed => ("导出失败: {}", "Export failed: {}"),
+    ExportUploading => (
+        "✅ 贴纸包「{}」导出完成，正在上传…",
+        "✅ Sticker set \"{}\" exported, uploading…"
+    ),
+    ExportDone => (
+        "📦 贴纸包「{}」：成功 {}，失败 {}。表情对应关系见 manifest.json",
+        "📦 Sticker set \"{}\": {} succeeded, {} failed. See manifest.json for the emoji of each sticker"
+    ),
+    ExportGifFailed => ("GIF转换失败，仅保留{}: {}", "GIF conversion failed, kept the {} only: {}"),
+    ExportTgsNote => (
+        "此版本未开启 TGS 渲染（tgs feature），保留原始文件",
+        "TGS rendering is not enabled in this build (tgs feature), kept the original file"
+    ),
+    ExportTgsKept => (
+        "\n⚠️ {} 个 TGS 动画贴纸未能转换为 GIF，已保留原始 .tgs 文件，原因见 manifest.json",
+        "\n⚠️ {} TGS animated stickers could not be converted to GIF and were kept as original .tgs files; see manifest.json for the reason"
+    ),
+
+    // 命令说明
+    CmdHelp => ("显示此帮助信息", "Show this help message"),
+    CmdStart => ("开始使用bot", "Start using the bot"),
+    CmdMode => ("切换工作模式", "Switch working mode"),
+    CmdQuota => ("查看今日用量", "Show today's usage"),
+    CmdLang => ("切换语言：/lang <zh|en|auto>", "Switch language: /lang <zh|en|auto>"),
+    CmdSticker => ("回复一条消息，将其中的媒体转为贴纸", "Reply to a message to convert its media into a sticker"),
+    CmdGif => ("回复一条消息，将其中的媒体转为 GIF", "Reply to a message to convert its media into a GIF"),
+    CmdEmoji => (
+        "回复一条消息，将其中的媒体转为自定义表情 (100x100)",
+        "Reply to a message to convert its media into a custom emoji (100x100)"
+    ),
+    CmdInfo => ("回复一条消息，查看其中媒体的信息", "Reply to a message to show info about its media"),
+    CmdRemoveBg => (
+        "设置本聊天是否去除图片背景",
+        "Set whether to remove image backgrounds in this chat"
+    ),
+    CmdSearch => ("按表情或关键词搜索处理过的贴纸", "Search converted stickers by emoji or keyword"),
+    CmdNewPack => ("回复一条媒体消息，创建贴纸包", "Reply to a media message to create a sticker set"),
+    CmdAddSticker => ("回复一条媒体消息，添加到贴纸包", "Reply to a media message to add it to a sticker set"),
+    CmdRemoveSticker => ("回复贴纸，将其从贴纸包删除", "Reply to a sticker to remove it from its set"),
+    CmdMoveSticker => ("回复贴纸，移动到指定位置", "Reply to a sticker to move it to a position"),
+    CmdAddGrid => (
+        "回复一条媒体消息，切分为自定义表情网格并添加到表情包",
+        "Reply to a media message to split it into an emoji grid and add it to an emoji set"
+    ),
+    CmdSetPackIcon => ("回复一条媒体消息，设为贴纸包图标", "Reply to a media message to set it as the set icon"),
+    CmdPacks => ("查看你的贴纸包", "List your sticker sets"),
+    CmdClonePack => ("复制一个或多个贴纸包", "Copy one or more sticker sets"),
+    CmdExportPack => ("导出整个贴纸包为压缩包", "Export a whole sticker set as an archive"),
+    CmdAllow => ("加入白名单", "Add to the whitelist"),
+    CmdDeny => ("移出白名单", "Remove from the whitelist"),
+    CmdBlock => ("加入黑名单", "Add to the deny-list"),
+    CmdUnblock => ("移出黑名单", "Remove from the deny-list"),
+    CmdAllowed => ("查看访问规则", "Show access rules"),
+}
+
+/// 命令在菜单中的本地化说明
+pub fn command_description(command: &str) -> Option<Msg> {
+    Some(match command {
+        "help" => Msg::CmdHelp,
+        "start" => Msg::CmdStart,
+        "mode" => Msg::CmdMode,
+        "quota" => Msg::CmdQuota,
+        "lang" => Msg::CmdLang,
+        "sticker" => Msg::CmdSticker,
+        "gif" => Msg::CmdGif,
+        "emoji" => Msg::CmdEmoji,
+        "info" => Msg::CmdInfo,
+        "search" => Msg::CmdSearch,
+        "removebg" => Msg::CmdRemoveBg,
+        "newpack" => Msg::CmdNewPack,
+        "addsticker" => Msg::CmdAddSticker,
+        "removesticker" => Msg::CmdRemoveSticker,
+        "movesticker" => Msg::CmdMoveSticker,
+        "addgrid" => Msg::CmdAddGrid,
+        "setpackicon" => Msg::CmdSetPackIcon,
+        "packs" => Msg::CmdPacks,
+        "clonepack" => Msg::CmdClonePack,
+        "exportpack" => Msg::CmdExportPack,
+        "allow" => Msg::CmdAllow,
+        "deny" => Msg::CmdDeny,
+        "block" => Msg::CmdBlock,
+        "unblock" => Msg::CmdUnblock,
+        "allowed" => Msg::CmdAllowed,
+        _ => return None,
+    })
+}
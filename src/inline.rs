@@ -0,0 +1,80 @@
+use teloxide::prelude::*;
+use teloxide::types::{
+    FileId, InlineQueryResult, InlineQueryResultCachedDocument, InlineQueryResultCachedSticker,
+};
+
+use crate::auth::SharedAuth;
+use crate::history::{HistoryEntry, SharedHistory};
+use crate::retry::RetryAfterExt;
+
+/// 每页最多返回的结果数（Telegram 限制为 50）
+const PAGE_SIZE: usize = 50;
+/// 客户端缓存结果的秒数，较短以便新处理的结果尽快出现
+const CACHE_TIME: u32 = 10;
+
+/// 将历史记录转为内联结果：贴纸直接发送，其余（GIF 等）作为文档发送
+fn inline_result(index: usize, entry: HistoryEntry) -> InlineQueryResult {
+    let id = format!("{}-{}", entry.time, index);
+    let file_id = FileId(entry.file_id);
+    if entry.is_sticker {
+        InlineQueryResultCachedSticker::new(id, file_id).into()
+    } else {
+        let mut title: Vec<String> = entry.meta.emojis.clone();
+        title.extend(entry.meta.keywords.iter().map(|k| format!("#{}", k)));
+        let title = if title.is_empty() {
+            format!("{:?}", entry.target)
+        } else {
+            title.join(" ")
+        };
+        InlineQueryResultCachedDocument::new(id, title, file_id).into()
+    }
+}
+
+/// 内联查询：`@机器人 表情或关键词` 搜索用户处理过的结果，最新的在前
+pub async fn inline_query_handler(
+    bot: Bot,
+    query: InlineQuery,
+    auth: SharedAuth,
+    history: SharedHistory,
+) -> anyhow::Result<()> {
+    let user_id = query.from.id;
+    // 内联查询没有所在聊天，按用户的私聊授权
+    if !auth
+        .is_authorized(&bot, Some(user_id), ChatId::from(user_id))
+        .await
+    {
+        log::warn!("未授权的内联查询，用户: {}", user_id);
+        bot.answer_inline_query(query.id, Vec::<InlineQueryResult>::new())
+            .is_personal(true)
+            .send_retry()
+            .await?;
+        return Ok(());
+    }
+
+    let offset: usize = query.offset.parse().unwrap_or(0);
+    let entries = history.search(user_id, &query.query, offset, PAGE_SIZE);
+    let next_offset = if entries.len() == PAGE_SIZE {
+        (offset + PAGE_SIZE).to_string()
+    } else {
+        String::new()
+    };
+    log::debug!(
+        "内联查询，用户: {}, 查询: {:?}, 结果: {}",
+        user_id,
+        query.query,
+        entries.len()
+    );
+
+    let results: Vec<InlineQueryResult> = entries
+        .into_iter()
+        .enumerate()
+        .map(|(index, entry)| inline_result(offset + index, entry))
+        .collect();
+    bot.answer_inline_query(query.id, results)
+        .is_personal(true)
+        .cache_time(CACHE_TIME)
+        .next_offset(next_offset)
+        .send_retry()
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,233 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use teloxide::types::UserId;
+
+use crate::config::env_or;
+use crate::i18n::Lang;
+use crate::storage::{JsonWriter, load_json, to_json};
+use crate::tr;
+
+const USAGE_FILE: &str = "usage.json";
+
+/// 限流与配额配置
+#[derive(Clone, Copy, Debug)]
+pub struct LimitConfig {
+    /// 令牌桶容量，即允许的突发请求数
+    pub burst: u32,
+    /// 每分钟补充的令牌数
+    pub per_minute: u32,
+    /// 每日转换次数上限，0 表示不限制
+    pub daily_conversions: u32,
+    /// 每日处理的输入字节数上限，0 表示不限制
+    pub daily_bytes: u64,
+}
+
+impl LimitConfig {
+    /// 从环境变量读取配置
+    pub fn from_env() -> Self {
+        Self {
+            burst: env_or("RATE_LIMIT_BURST", 5).max(1),
+            per_minute: env_or("RATE_LIMIT_PER_MINUTE", 10),
+            daily_conversions: env_or("DAILY_CONVERSION_QUOTA", 0),
+            daily_bytes: env_or::<u64>("DAILY_QUOTA_MB", 0) * 1024 * 1024,
+        }
+    }
+}
+
+/// 单个用户的令牌桶
+#[derive(Clone, Copy, Debug)]
+struct TokenBucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+/// 单个用户当日的用量
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+pub struct DailyUsage {
+    /// 自 UNIX 纪元起的天数（UTC）
+    pub day: u64,
+    pub conversions: u32,
+    pub bytes: u64,
+}
+
+/// 被拒绝的原因
+#[derive(Clone, Copy, Debug)]
+pub enum LimitExceeded {
+    /// 请求过快，需要等待的秒数
+    RateLimited { retry_after_secs: u64 },
+    /// 当日转换次数已用完
+    DailyConversions { limit: u32 },
+    /// 当日处理字节数已用完
+    DailyBytes { limit: u64 },
+}
+
+impl LimitExceeded {
+    /// 提示给用户的消息
+    pub fn message(&self, lang: Lang) -> String {
+        match self {
+            LimitExceeded::RateLimited { retry_after_secs } => {
+                tr!(lang, RateLimited, retry_after_secs)
+            }
+            LimitExceeded::DailyConversions { limit } => tr!(lang, DailyConversionsExceeded, limit),
+            LimitExceeded::DailyBytes { limit } => {
+                tr!(lang, DailyBytesExceeded, limit / (1024 * 1024))
+            }
+        }
+    }
+}
+
+/// 每用户的令牌桶限流与每日配额
+pub struct UsageLimiter {
+    config: LimitConfig,
+    buckets: Mutex<HashMap<UserId, TokenBucket>>,
+    usage: Mutex<HashMap<UserId, DailyUsage>>,
+    writer: JsonWriter,
+}
+
+pub type SharedLimiter = Arc<UsageLimiter>;
+
+/// 一次请求中需要逐项检查配额的用户，用于一个输入产生多个结果的情况（如压缩包）
+#[derive(Clone, Copy)]
+pub struct UserQuota<'a> {
+    pub limiter: &'a UsageLimiter,
+    pub user_id: UserId,
+}
+
+impl UserQuota<'_> {
+    /// 本次请求已转换 `converted` 个文件时，检查每日转换次数是否还够再转换一个
+    ///
+    /// 字节配额已在下载前按输入文件的大小检查过。
+    pub fn check_next(&self, converted: u32) -> Result<(), LimitExceeded> {
+        self.limiter.check_quota(self.user_id, converted + 1, 0)
+    }
+}
+
+/// 当前日期（自 UNIX 纪元起的天数，UTC）
+fn today() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() / 86400)
+        .unwrap_or(0)
+}
+
+impl UsageLimiter {
+    /// 创建限流器，并从数据目录恢复当日用量
+    pub fn new(config: LimitConfig) -> Result<Self> {
+        let usage: HashMap<UserId, DailyUsage> = load_json(USAGE_FILE)?.unwrap_or_default();
+        log::info!(
+            "限流配置: 突发 {} 次, 每分钟 {} 次, 每日 {} 次, 每日 {}MB (0 表示不限制)",
+            config.burst,
+            config.per_minute,
+            config.daily_conversions,
+            config.daily_bytes / (1024 * 1024)
+        );
+        Ok(Self {
+            config,
+            buckets: Mutex::new(HashMap::new()),
+            usage: Mutex::new(usage),
+            writer: JsonWriter::new(USAGE_FILE),
+        })
+    }
+
+    /// 检查用户是否还能一次发起 `conversions` 次转换（单个文件为 1，相册为项数），通过时消耗一个令牌
+    ///
+    /// `bytes` 为这些输入文件的总大小，用于检查字节配额。
+    pub fn check_batch(
+        &self,
+        user_id: UserId,
+        conversions: u32,
+        bytes: u64,
+    ) -> Result<(), LimitExceeded> {
+        self.check_quota(user_id, conversions, bytes)?;
+
+        if self.config.per_minute == 0 {
+            return Ok(());
+        }
+        let capacity = self.config.burst as f64;
+        let refill_per_sec = self.config.per_minute as f64 / 60.0;
+        let mut buckets = self.buckets.lock().unwrap();
+        let bucket = buckets.entry(user_id).or_insert(TokenBucket {
+            tokens: capacity,
+            updated_at: Instant::now(),
+        });
+        let elapsed = bucket.updated_at.elapsed().as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * refill_per_sec).min(capacity);
+        bucket.updated_at = Instant::now();
+        if bucket.tokens < 1.0 {
+            let retry_after_secs = ((1.0 - bucket.tokens) / refill_per_sec).ceil() as u64;
+            return Err(LimitExceeded::RateLimited { retry_after_secs });
+        }
+        bucket.tokens -= 1.0;
+        Ok(())
+    }
+
+    /// 只检查每日配额是否还够 `conversions` 次转换和 `bytes` 字节，不消耗令牌
+    pub fn check_quota(
+        &self,
+        user_id: UserId,
+        conversions: u32,
+        bytes: u64,
+    ) -> Result<(), LimitExceeded> {
+        let usage = self.usage(user_id);
+        if self.config.daily_conversions > 0
+            && usage.conversions + conversions > self.config.daily_conversions
+        {
+            return Err(LimitExceeded::DailyConversions {
+                limit: self.config.daily_conversions,
+            });
+        }
+        if self.config.daily_bytes > 0 && usage.bytes + bytes > self.config.daily_bytes {
+            return Err(LimitExceeded::DailyBytes {
+                limit: self.config.daily_bytes,
+            });
+        }
+        Ok(())
+    }
+
+    /// 记录一次成功的转换
+    pub async fn record(&self, user_id: UserId, bytes: u64) -> Result<()> {
+        self.record_batch(user_id, 1, bytes).await
+    }
+
+    /// 记录一次请求中成功的 `conversions` 次转换（如压缩包中的每个文件）
+    pub async fn record_batch(&self, user_id: UserId, conversions: u32, bytes: u64) -> Result<()> {
+        {
+            let mut usage = self.usage.lock().unwrap();
+            let day = today();
+            let entry = usage.entry(user_id).or_default();
+            if entry.day != day {
+                *entry = DailyUsage {
+                    day,
+                    ..Default::default()
+                };
+            }
+            entry.conversions += conversions;
+            entry.bytes += bytes;
+            // 只保留当日的记录
+            usage.retain(|_, u| u.day == day);
+        }
+        self.writer
+            .save(|| to_json(&*self.usage.lock().unwrap()))
+            .await
+    }
+
+    /// 用户当日的用量
+    pub fn usage(&self, user_id: UserId) -> DailyUsage {
+        let day = today();
+        match self.usage.lock().unwrap().get(&user_id) {
+            Some(usage) if usage.day == day => *usage,
+            _ => DailyUsage {
+                day,
+                ..Default::default()
+            },
+        }
+    }
+
+    pub fn config(&self) -> LimitConfig {
+        self.config
+    }
+}
@@ -4,17 +4,56 @@ use std::sync::{Arc, Mutex};
 use anyhow::{Context, Result};
 use dotenv::dotenv;
 use teloxide::prelude::*;
-use teloxide::types::ChatId;
-
+use teloxide::types::Me;
+
+mod album;
+mod archive;
+mod auth;
+mod background;
+mod cache;
+mod config;
+mod decode;
+mod effects;
+mod encode;
+mod export;
+mod grid;
 mod handlers;
+mod history;
+mod i18n;
+mod inline;
+mod limits;
+mod media;
+mod menu;
+mod metadata;
+mod options;
+mod packs;
 mod processors;
+mod quote;
+mod reply;
+mod retry;
 mod state;
-
+mod storage;
+mod text;
+mod transform;
+mod trim;
+
+use album::{AlbumCollector, QuoteCollector, SharedAlbums, SharedQuotes};
+use auth::{AuthService, SharedAuth};
+use cache::{CacheConfig, ResultCache, SharedCache};
+use encode::EncoderConfig;
 use handlers::{
-    BotCommand, command_handler, handle_file, unauthorized_access_handler,
-    unhandled_message_handler,
+    AdminCommand, BotCommand, admin_command_handler, command_handler, handle_file,
+    unauthorized_access_handler, unhandled_message_handler,
 };
-use state::ModeState;
+use history::{HistoryStore, SharedHistory};
+use i18n::Lang;
+use inline::inline_query_handler;
+use limits::{LimitConfig, SharedLimiter, UsageLimiter};
+use menu::register_commands;
+use packs::{PackCommand, PackStore, SharedPacks, pack_command_handler};
+use quote::quote_handler;
+use reply::{is_addressed_to_bot, is_group_chat};
+use state::{LangStore, ModeState, SharedLangs};
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -29,100 +68,104 @@ async fn main() -> Result<()> {
     let token = std::env::var("TELEGRAM_BOT_TOKEN")
         .context("未找到TELEGRAM_BOT_TOKEN环境变量。请在.env文件中设置或直接设置环境变量")?;
 
-    // 白名单逻辑
-    let allowed_chat_ids_opt: Option<Arc<Vec<ChatId>>> = match std::env::var("ALLOWED_CHAT_IDS") {
-        Ok(ids_str) => {
-            let ids: Vec<ChatId> = ids_str
-                .split(',')
-                .filter_map(|id_str| id_str.trim().parse::<i64>().ok().map(ChatId))
-                .collect();
-            if ids.is_empty() {
-                log::warn!(
-                    "ALLOWED_CHAT_IDS 设置为 '{}', 解析后白名单为空。机器人将不会授权任何用户。",
-                    ids_str
-                );
-            } else {
-                log::info!("白名单已启用。允许的聊天 ID: {:?}", ids);
-            }
-            Some(Arc::new(ids))
-        }
-        Err(_) => {
-            log::info!("未设置 ALLOWED_CHAT_IDS。机器人将响应所有用户。");
-            None
-        }
-    };
+    // 授权服务（白名单与管理员）
+    let auth: SharedAuth = Arc::new(AuthService::from_env()?);
+
+    // 每用户限流与每日配额
+    let limiter: SharedLimiter = Arc::new(UsageLimiter::new(LimitConfig::from_env())?);
 
     let bot = Bot::new(token);
 
+    // 按聊天类型和语言注册命令菜单，失败时不影响运行
+    if let Err(e) = register_commands(&bot, &auth).await {
+        log::warn!("注册命令菜单失败: {:?}", e);
+    }
+
     // 初始化模式状态
     let mode_state: ModeState = Arc::new(Mutex::new(HashMap::new()));
 
-    // 为命令处理程序创建过滤器闭包
-    let command_filter_ids_clone = allowed_chat_ids_opt.clone();
-    let command_auth_filter = move |msg: Message| match &command_filter_ids_clone {
-        Some(allowed_ids) => allowed_ids.contains(&msg.chat.id),
-        None => true,
-    };
+    // 用户创建的贴纸包记录
+    let packs: SharedPacks = Arc::new(PackStore::load()?);
+
+    // 用户通过 /lang 设置的语言
+    let langs: SharedLangs = Arc::new(LangStore::load()?);
+
+    // 处理历史（表情与关键词）
+    let history: SharedHistory = Arc::new(HistoryStore::load()?);
+
+    // 结果缓存：相同文件与选项直接重发之前的结果
+    let cache: SharedCache = Arc::new(ResultCache::load(CacheConfig::from_env())?);
 
-    // 为文件处理程序创建过滤器闭包
-    let file_filter_ids_clone = allowed_chat_ids_opt.clone();
-    let file_auth_and_type_filter = move |msg: Message| {
-        let authorized = match &file_filter_ids_clone {
-            Some(allowed_ids) => allowed_ids.contains(&msg.chat.id),
-            None => true,
-        };
-        if !authorized {
-            return false;
-        }
-        // 原始文件类型检查
-        msg.photo().is_some()
+    // 静态图片的编码配置
+    let encoder = EncoderConfig::from_env();
+    log::info!("静态图片编码配置: {:?}", encoder);
+
+    // 相册收集器
+    let albums: SharedAlbums = Arc::new(AlbumCollector::default());
+
+    // 语录收集器：一次转发的多条文字消息合成一张贴纸
+    let quotes: SharedQuotes = Arc::new(QuoteCollector::default());
+
+    // 媒体消息过滤器：群组中只处理呼叫了机器人的媒体（提及或回复机器人），
+    // 以及已开始收集的相册中的其余消息
+    let media_filter = |msg: Message, me: Me, albums: SharedAlbums| {
+        let is_media = msg.photo().is_some()
             || msg.document().is_some()
             || msg.sticker().is_some()
-            || msg.animation().is_some()
-    };
-
-    // 为未处理消息创建认证过滤器
-    let unhandled_message_auth_filter_ids = allowed_chat_ids_opt.clone();
-    let unhandled_message_auth_filter = move |msg: Message| match &unhandled_message_auth_filter_ids
-    {
-        Some(allowed_ids) => allowed_ids.contains(&msg.chat.id),
-        None => true,
+            || msg.animation().is_some();
+        let in_collecting_album = msg
+            .media_group_id()
+            .is_some_and(|group_id| albums.is_collecting(group_id));
+        is_media && (!is_group_chat(&msg) || is_addressed_to_bot(&msg, &me) || in_collecting_album)
     };
 
-    // 克隆 mode_state 用于各个分支
-    let mode_state_cmd = mode_state.clone();
-    let mode_state_file = mode_state.clone();
-    let mode_state_unhandled = mode_state.clone();
-
-    // 创建处理器
-    let handler = Update::filter_message()
-        .branch(
-            dptree::entry()
-                .filter_command::<BotCommand>()
-                .filter(command_auth_filter)
-                .endpoint(move |bot: Bot, cmd: BotCommand, msg: Message| {
-                    let mode_state = mode_state_cmd.clone();
-                    async move { command_handler(bot, msg, cmd, mode_state).await }
-                }),
-        )
-        .branch(dptree::filter(file_auth_and_type_filter).endpoint(
-            move |bot: Bot, msg: Message| {
-                let mode_state = mode_state_file.clone();
-                async move { handle_file(bot, msg, mode_state).await }
-            },
-        ))
+    // 创建处理器：所有分支共享同一个授权服务，并按发送者确定界面语言
+    let message_handler = Update::filter_message()
+        .map(|msg: Message, langs: SharedLangs| Lang::of(&msg, &langs))
         .branch(
-            dptree::entry()
-                .filter(unhandled_message_auth_filter)
-                .endpoint(move |bot: Bot, msg: Message| {
-                    let mode_state = mode_state_unhandled.clone();
-                    async move { unhandled_message_handler(bot, msg, mode_state).await }
-                }),
+            dptree::filter_async(|bot: Bot, msg: Message, auth: SharedAuth| async move {
+                auth.is_message_authorized(&bot, &msg).await
+            })
+            .branch(
+                dptree::entry()
+                    .filter_command::<AdminCommand>()
+                    .filter(|msg: Message, auth: SharedAuth| {
+                        msg.from.as_ref().is_some_and(|user| auth.is_admin(user.id))
+                    })
+                    .endpoint(admin_command_handler),
+            )
+            .branch(
+                dptree::entry()
+                    .filter_command::<BotCommand>()
+                    .endpoint(command_handler),
+            )
+            .branch(
+                dptree::entry()
+                    .filter_command::<PackCommand>()
+                    .endpoint(pack_command_handler),
+            )
+            .branch(dptree::filter(media_filter).endpoint(handle_file))
+            .branch(
+                // 私聊中转发来的文字消息生成语录贴纸
+                dptree::filter(|msg: Message| {
+                    msg.text().is_some() && msg.forward_origin().is_some() && !is_group_chat(&msg)
+                })
+                .endpoint(quote_handler),
+            )
+            .branch(dptree::endpoint(unhandled_message_handler)),
         )
         .branch(dptree::endpoint(unauthorized_access_handler));
 
+    // 内联查询：搜索用户处理过的贴纸
+    let handler = dptree::entry()
+        .branch(message_handler)
+        .branch(Update::filter_inline_query().endpoint(inline_query_handler));
+
     // 启动机器人
     Dispatcher::builder(bot, handler)
+        .dependencies(dptree::deps![
+            mode_state, auth, limiter, albums, quotes, packs, history, cache, langs, encoder
+        ])
         .enable_ctrlc_handler()
         .build()
         .dispatch()
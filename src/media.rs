@@ -0,0 +1,390 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+use teloxide::RequestError;
+use teloxide::prelude::*;
+use teloxide::types::{FileId, FileMeta, StickerFormat};
+use tempfile::{Builder, NamedTempFile};
+use tokio::fs as tokio_fs;
+
+use crate::archive::convert_archive;
+use crate::decode::{SVG_MIME, image_dimensions, is_svg};
+use crate::encode::{EncoderConfig, TooLarge};
+use crate::grid::convert_grid;
+use crate::i18n::Lang;
+use crate::limits::UserQuota;
+use crate::options::ProcessOptions;
+use crate::processors::{
+    EMOJI_MAX_BYTES, STATIC_THUMBNAIL_MAX_BYTES, VIDEO_THUMBNAIL_MAX_BYTES, probe_video,
+    process_emoji_image, process_emoji_webm, process_image, process_video_to_gif, process_webm,
+};
+use crate::retry::RetryAfterExt;
+use crate::state::Mode;
+use crate::tr;
+
+/// ZIP 压缩包的 MIME 类型
+pub const ZIP_MIME: &str = "application/zip";
+/// 结果压缩包的大小上限（Telegram Bot API 上传限制 50MB）
+pub const MAX_OUTPUT_SIZE: u64 = 50 * 1024 * 1024;
+
+/// 处理目标
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Target {
+    /// Telegram 贴纸（512px WebP 或 PNG / VP9 WebM）
+    Sticker,
+    /// GIF 文件（图片原样作为文档返回）
+    Gif,
+    /// 自定义表情（100x100 WebP 或 PNG / VP9 WebM）
+    Emoji,
+    /// 贴纸包图标（100x100 WebP 或 PNG 不超过 128KB / VP9 WebM 不超过 32KB）
+    Thumbnail,
+}
+
+impl From<Mode> for Target {
+    fn from(mode: Mode) -> Self {
+        match mode {
+            Mode::StickerOptimize => Target::Sticker,
+            Mode::GifDownload => Target::Gif,
+        }
+    }
+}
+
+/// 从消息中提取可处理的媒体文件
+///
+/// 消息中没有媒体时返回 `Ok(None)`；媒体类型不受支持时返回给用户的提示。
+pub fn extract_media_file(msg: &Message, lang: Lang) -> Result<Option<FileMeta>, String> {
+    if let Some(photo) = msg.photo() {
+        Ok(Some(photo.last().expect("照片列表不应为空").file.clone()))
+    } else if let Some(document) = msg.document() {
+        match document
+            .mime_type
+            .as_ref()
+            .map(|mime| mime.to_string())
+            .as_deref()
+        {
+            Some(mime)
+                if mime.starts_with("image/")
+                    || mime.starts_with("video/")
+                    || mime == "application/octet-stream"
+                    || mime == ZIP_MIME
+                    || mime == "application/x-zip-compressed" =>
+            {
+                Ok(Some(document.file.clone()))
+            }
+            Some(mime) => Err(tr!(lang, UnsupportedDocument, mime)),
+            None => Ok(Some(document.file.clone())),
+        }
+    } else if let Some(sticker) = msg.sticker() {
+        Ok(Some(sticker.file.clone()))
+    } else if let Some(animation) = msg.animation() {
+        match animation
+            .mime_type
+            .as_ref()
+            .map(|mime| mime.to_string())
+            .as_deref()
+        {
+            Some(mime) if mime.starts_with("video/") => Ok(Some(animation.file.clone())),
+            Some(mime) => Err(tr!(lang, UnsupportedAnimation, mime)),
+            None => Ok(Some(animation.file.clone())),
+        }
+    } else {
+        Ok(None)
+    }
+}
+
+/// 下载 Telegram 文件到临时文件，返回临时文件和文件大小
+pub async fn download_file(bot: &Bot, file_id: FileId) -> Result<(NamedTempFile, u64)> {
+    let tg_file = bot.get_file(file_id).send_retry().await?;
+    let input_temp_file = NamedTempFile::new().context("无法创建输入临时文件")?;
+
+    let file_url = format!(
+        "https://api.telegram.org/file/bot{}/{}",
+        bot.token(),
+        tg_file.path
+    );
+    let bytes = reqwest::get(&file_url).await?.bytes().await?;
+    tokio_fs::write(input_temp_file.path(), &bytes)
+        .await
+        .context("无法写入输入临时文件")?;
+    Ok((input_temp_file, bytes.len() as u64))
+}
+
+/// 检测到的文件类型
+#[derive(Clone, Debug)]
+pub struct DetectedType {
+    pub is_image: bool,
+    pub is_video: bool,
+    pub mime: String,
+}
+
+/// 使用 infer 检测文件类型，infer 无法识别的 SVG 另行检测
+pub fn detect_type(path: &Path) -> Result<DetectedType> {
+    let detected_type_result = infer::get_from_path(path).context("无法从路径获取类型信息推断")?;
+    let maybe_svg = detected_type_result.is_none_or(|info| info.mime_type() == "text/xml");
+    if cfg!(feature = "svg") && maybe_svg && is_svg(path)? {
+        return Ok(DetectedType {
+            is_image: true,
+            is_video: false,
+            mime: SVG_MIME.to_string(),
+        });
+    }
+    Ok(match detected_type_result {
+        Some(info) => DetectedType {
+            is_image: info.mime_type().starts_with("image/"),
+            is_video: info.mime_type().starts_with("video/"),
+            mime: info.mime_type().to_string(),
+        },
+        None => DetectedType {
+            is_image: false,
+            is_video: false,
+            mime: "未知 (infer无法识别)".to_string(),
+        },
+    })
+}
+
+/// 可以提示给用户的处理失败原因，原始错误只写入日志
+#[derive(Debug)]
+pub enum Failure {
+    /// 已按用户的语言生成的消息
+    Localized(String),
+    ImageDecode,
+    VideoProbe,
+    VideoDecode,
+    VideoEncode,
+    GifEncode,
+    TgsRender,
+    FontMissing,
+    QuoteNoText,
+}
+
+impl Failure {
+    /// 提示给用户的消息
+    pub fn message(&self, lang: Lang) -> String {
+        match self {
+            Failure::Localized(message) => message.clone(),
+            Failure::ImageDecode => tr!(lang, ImageDecodeFailed),
+            Failure::VideoProbe => tr!(lang, VideoProbeFailed),
+            Failure::VideoDecode => tr!(lang, VideoDecodeFailed),
+            Failure::VideoEncode => tr!(lang, VideoEncodeFailed),
+            Failure::GifEncode => tr!(lang, GifEncodeFailed),
+            Failure::TgsRender => tr!(lang, TgsRenderFailed),
+            Failure::FontMissing => tr!(lang, FontMissing),
+            Failure::QuoteNoText => tr!(lang, QuoteNoText),
+        }
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message(Lang::default()))
+    }
+}
+
+impl std::error::Error for Failure {}
+
+/// 提示给用户的处理失败原因
+///
+/// 按用户的语言说明超出大小限制、[`Failure`] 和 Telegram 返回的错误，其他错误只提示内部错误，原始原因只写入日志。
+/// `Failure` 可以是错误本身，也可以是用 `context` 附加的上下文。
+pub fn error_message(e: &anyhow::Error, lang: Lang) -> String {
+    if let Some(too_large) = e.downcast_ref::<TooLarge>() {
+        too_large.message(lang)
+    } else if let Some(failure) = e.downcast_ref::<Failure>() {
+        failure.message(lang)
+    } else if let Some(request) = e.downcast_ref::<RequestError>() {
+        request.to_string()
+    } else {
+        tr!(lang, InternalError)
+    }
+}
+
+/// 读取图片或视频的尺寸
+pub fn media_dimensions(path: &Path, detected: &DetectedType) -> Result<(u32, u32)> {
+    if detected.is_image {
+        image_dimensions(path).context(Failure::ImageDecode)
+    } else if detected.is_video {
+        let info = probe_video(path)?;
+        Ok((info.width, info.height))
+    } else {
+        Err(anyhow!("不是图片或视频 ({})", detected.mime))
+    }
+}
+
+/// 处理结果
+pub struct Converted {
+    /// 持有临时文件，离开作用域时删除
+    _file: NamedTempFile,
+    pub path: PathBuf,
+    /// 是否作为贴纸发送，否则作为文档发送
+    pub is_sticker: bool,
+    /// 随结果发送的说明文字
+    pub caption: Option<String>,
+    /// 计入每日配额的转换次数，压缩包为其中成功转换的文件数
+    pub conversions: u32,
+}
+
+impl Converted {
+    pub fn new(file: NamedTempFile, is_sticker: bool) -> Self {
+        let path = file.path().to_path_buf();
+        Self {
+            _file: file,
+            path,
+            is_sticker,
+            caption: None,
+            conversions: 1,
+        }
+    }
+
+    /// 作为贴纸上传时的格式，非贴纸结果返回 None
+    pub fn sticker_format(&self) -> Option<StickerFormat> {
+        if !self.is_sticker {
+            return None;
+        }
+        match self.path.extension().and_then(|ext| ext.to_str()) {
+            Some("webm") => Some(StickerFormat::Video),
+            Some("tgs") => Some(StickerFormat::Animated),
+            _ => Some(StickerFormat::Static),
+        }
+    }
+}
+
+/// 创建带后缀的输出临时文件
+pub fn output_tempfile(suffix: &str) -> Result<NamedTempFile> {
+    Builder::new()
+        .suffix(suffix)
+        .tempfile()
+        .with_context(|| format!("无法创建{}输出临时文件", suffix))
+}
+
+/// 按照处理目标转换输入文件，ZIP 压缩包会逐项转换后重新打包，指定网格的自定义表情切分后打包
+///
+/// `quota` 为需要限流的用户，压缩包中的每个文件转换前都会检查其每日配额。
+pub async fn convert(
+    input: NamedTempFile,
+    target: Target,
+    options: &ProcessOptions,
+    encoder: &EncoderConfig,
+    lang: Lang,
+    quota: Option<UserQuota<'_>>,
+) -> Result<Converted> {
+    let detected = detect_type(input.path())?;
+    if detected.mime == ZIP_MIME {
+        return convert_archive(input.path(), target, options, encoder, lang, quota).await;
+    }
+    if target == Target::Emoji
+        && let Some(grid) = &options.grid
+    {
+        return convert_grid(input.path(), &detected, grid, options, encoder, lang).await;
+    }
+    convert_single(input, &detected, target, options, encoder, lang).await
+}
+
+/// 转换单个图片或视频文件
+pub async fn convert_single(
+    input: NamedTempFile,
+    detected: &DetectedType,
+    target: Target,
+    options: &ProcessOptions,
+    encoder: &EncoderConfig,
+    lang: Lang,
+) -> Result<Converted> {
+    let input_path = input.path().to_path_buf();
+    log::debug!(
+        "输入: {:?}, 检测到的类型: {}, 目标: {:?}",
+        input_path,
+        detected.mime,
+        target
+    );
+
+    match target {
+        // GIF 模式下直接发送图片作为文档
+        Target::Gif if detected.is_image => Ok(Converted::new(input, false)),
+        Target::Gif if detected.is_video => {
+            let output = output_tempfile(".gif")?;
+            process_video_to_gif(&input_path, output.path())
+                .await
+                .context("GIF转换失败")?;
+            Ok(Converted::new(output, false))
+        }
+        Target::Gif => Err(Failure::Localized(tr!(lang, UnsupportedForGif, detected.mime)).into()),
+        Target::Sticker | Target::Emoji | Target::Thumbnail if detected.is_image => {
+            let output = output_tempfile(encoder.suffix())?;
+            let output_path = output.path();
+            match target {
+                Target::Emoji => {
+                    process_emoji_image(&input_path, output_path, options, encoder, EMOJI_MAX_BYTES)
+                        .await
+                }
+                Target::Thumbnail => {
+                    process_emoji_image(
+                        &input_path,
+                        output_path,
+                        options,
+                        encoder,
+                        STATIC_THUMBNAIL_MAX_BYTES,
+                    )
+                    .await
+                }
+                _ => process_image(&input_path, output_path, options, encoder).await,
+            }
+            .context("图片处理失败")?;
+            Ok(Converted::new(output, true))
+        }
+        Target::Sticker | Target::Emoji | Target::Thumbnail if detected.is_video => {
+            let output = output_tempfile(".webm")?;
+            match target {
+                Target::Emoji => {
+                    process_emoji_webm(&input_path, output.path(), options, EMOJI_MAX_BYTES).await
+                }
+                Target::Thumbnail => {
+                    process_emoji_webm(
+                        &input_path,
+                        output.path(),
+                        options,
+                        VIDEO_THUMBNAIL_MAX_BYTES,
+                    )
+                    .await
+                }
+                _ => process_webm(&input_path, output.path(), options).await,
+            }
+            .context("视频处理失败")?;
+            Ok(Converted::new(output, true))
+        }
+        Target::Sticker | Target::Emoji | Target::Thumbnail => {
+            Err(Failure::Localized(tr!(lang, UnsupportedForSticker, detected.mime)).into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::anyhow;
+
+    use super::*;
+
+    /// 附加在上下文中的 Failure 按用户的语言提示，原始原因不会提示给用户
+    #[test]
+    fn failure_context_is_localized() {
+        let e = Err::<(), _>(anyhow!("FFprobe命令执行失败"))
+            .context(Failure::VideoProbe)
+            .context("视频处理失败")
+            .unwrap_err();
+        assert_eq!(error_message(&e, Lang::En), tr!(Lang::En, VideoProbeFailed));
+    }
+
+    #[test]
+    fn unknown_errors_are_internal() {
+        let e = anyhow!("无法创建临时文件");
+        assert_eq!(error_message(&e, Lang::En), tr!(Lang::En, InternalError));
+    }
+
+    #[test]
+    fn too_large_is_found_below_context() {
+        let too_large = TooLarge::Gif { size: 3, limit: 2 };
+        let message = too_large.message(Lang::En);
+        let e = Err::<(), _>(too_large).context("GIF转换失败").unwrap_err();
+        assert_eq!(error_message(&e, Lang::En), message);
+    }
+}
@@ -0,0 +1,94 @@
+use anyhow::Result;
+use teloxide::prelude::*;
+use teloxide::types::{BotCommand as MenuCommand, BotCommandScope, Recipient};
+use teloxide::utils::command::BotCommands;
+
+use crate::auth::SharedAuth;
+use crate::handlers::{AdminCommand, BotCommand};
+use crate::i18n::{Lang, command_description};
+use crate::packs::PackCommand;
+use crate::retry::RetryAfterExt;
+
+/// 命令菜单的适用范围
+#[derive(Clone, Copy, Debug)]
+enum MenuKind {
+    /// 群组：基本命令
+    Group,
+    /// 私聊：基本命令与贴纸包命令
+    Private,
+    /// 管理员私聊：所有命令
+    Admin,
+}
+
+/// 由命令枚举生成菜单，说明替换为本地化文本
+fn menu_commands(kind: MenuKind, lang: Lang) -> Vec<MenuCommand> {
+    let mut commands = BotCommand::bot_commands();
+    if matches!(kind, MenuKind::Private | MenuKind::Admin) {
+        commands.extend(PackCommand::bot_commands());
+    }
+    if matches!(kind, MenuKind::Admin) {
+        commands.extend(AdminCommand::bot_commands());
+    }
+    commands
+        .into_iter()
+        .map(|mut command| {
+            command.command = command.command.trim_start_matches('/').to_string();
+            if let Some(description) = command_description(&command.command) {
+                command.description = description.text(lang).to_string();
+            }
+            command
+        })
+        .collect()
+}
+
+/// 为一个范围注册所有语言的菜单，未匹配语言的用户看到默认语言的菜单
+async fn set_scope_commands(bot: &Bot, scope: BotCommandScope, kind: MenuKind) -> Result<()> {
+    bot.set_my_commands(menu_commands(kind, Lang::default()))
+        .scope(scope.clone())
+        .send_retry()
+        .await?;
+    for lang in Lang::ALL {
+        bot.set_my_commands(menu_commands(kind, lang))
+            .scope(scope.clone())
+            .language_code(lang.code())
+            .send_retry()
+            .await?;
+    }
+    Ok(())
+}
+
+/// 启动时注册命令菜单：群组、私聊和管理员私聊各自使用不同的命令列表
+///
+/// 菜单由命令枚举生成，新增命令后重启即可同步。
+pub async fn register_commands(bot: &Bot, auth: &SharedAuth) -> Result<()> {
+    set_scope_commands(bot, BotCommandScope::Default, MenuKind::Group).await?;
+    set_scope_commands(bot, BotCommandScope::AllGroupChats, MenuKind::Group).await?;
+    set_scope_commands(bot, BotCommandScope::AllPrivateChats, MenuKind::Private).await?;
+    for admin in auth.admins() {
+        let scope = BotCommandScope::Chat {
+            chat_id: Recipient::Id(ChatId::from(*admin)),
+        };
+        // 管理员尚未与机器人开始对话时无法设置
+        if let Err(e) = set_scope_commands(bot, scope, MenuKind::Admin).await {
+            log::warn!("为管理员 {} 注册命令菜单失败: {}", admin, e);
+        }
+    }
+    log::info!("已注册命令菜单");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 每个命令都有本地化的菜单说明
+    #[test]
+    fn every_command_has_description() {
+        let missing: Vec<String> = menu_commands(MenuKind::Admin, Lang::default())
+            .into_iter()
+            .map(|command| command.command)
+            .filter(|command| command_description(command).is_none())
+            .collect();
+        assert!(missing.is_empty(), "缺少菜单说明的命令: {:?}", missing);
+    }
+}
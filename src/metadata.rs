@@ -0,0 +1,172 @@
+use serde::{Deserialize, Serialize};
+use teloxide::types::Message;
+
+/// Telegram 每个贴纸最多关联的表情数
+const MAX_EMOJIS: usize = 20;
+/// Telegram 每个贴纸最多关联的关键词数
+const MAX_KEYWORDS: usize = 20;
+/// Telegram 关键词的总长度上限
+const MAX_KEYWORDS_LEN: usize = 64;
+
+/// 贴纸的表情与关键词，从消息说明文字中解析，例如 `😂🤣 #funny #cat`
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StickerMeta {
+    pub emojis: Vec<String>,
+    pub keywords: Vec<String>,
+}
+
+/// 是否为可以开始一个表情的字符
+fn is_emoji_char(c: char) -> bool {
+    matches!(c as u32,
+        0x1F000..=0x1FAFF
+        | 0x2600..=0x27BF
+        | 0x2300..=0x23FF
+        | 0x2B00..=0x2BFF
+        | 0x2190..=0x21FF
+        | 0x25A0..=0x25FF
+        | 0x2934..=0x2935
+        | 0x3030
+        | 0x303D
+        | 0x3297
+        | 0x3299
+        | 0x00A9
+        | 0x00AE
+        | 0x203C
+        | 0x2049
+        | 0x2122
+        | 0x2139
+        | 0x24C2)
+}
+
+/// 是否为修饰前一个表情的字符（变体选择符、肤色、组合键帽、标签序列）
+fn is_emoji_modifier(c: char) -> bool {
+    matches!(c as u32,
+        0xFE0F | 0x20E3 | 0x1F3FB..=0x1F3FF | 0xE0020..=0xE007F)
+}
+
+/// 是否为区域指示符（两个组成一个国旗）
+fn is_regional_indicator(c: char) -> bool {
+    matches!(c as u32, 0x1F1E6..=0x1F1FF)
+}
+
+/// 从文本中提取完整的表情序列，保留 ZWJ 组合与修饰符
+fn split_emojis(text: &str) -> Vec<String> {
+    let mut emojis = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if !is_emoji_char(c) {
+            continue;
+        }
+        let mut emoji = c.to_string();
+        if is_regional_indicator(c)
+            && let Some(&next) = chars.peek()
+            && is_regional_indicator(next)
+        {
+            emoji.push(next);
+            chars.next();
+        }
+        while let Some(&next) = chars.peek() {
+            if is_emoji_modifier(next) {
+                emoji.push(next);
+                chars.next();
+            } else if next == '\u{200D}' {
+                emoji.push(next);
+                chars.next();
+                if let Some(joined) = chars.next() {
+                    emoji.push(joined);
+                }
+            } else {
+                break;
+            }
+        }
+        emojis.push(emoji);
+    }
+    emojis
+}
+
+impl StickerMeta {
+    /// 解析文本中的表情和 `#关键词`，其余内容忽略
+    pub fn parse(text: &str) -> Self {
+        Self::extract(text).1
+    }
+
+    /// 解析文本中的表情和 `#关键词`，同时返回去除它们后剩余的词
+    pub fn extract(text: &str) -> (String, Self) {
+        let mut meta = Self::default();
+        let mut rest = Vec::new();
+        for word in text.split_whitespace() {
+            if let Some(keyword) = word.strip_prefix('#') {
+                meta.push_keyword(keyword);
+                continue;
+            }
+            let emojis = split_emojis(word);
+            if emojis.is_empty() {
+                rest.push(word);
+            }
+            for emoji in emojis {
+                meta.push_emoji(emoji);
+            }
+        }
+        (rest.join(" "), meta)
+    }
+
+    fn push_emoji(&mut self, emoji: String) {
+        if self.emojis.len() < MAX_EMOJIS && !self.emojis.contains(&emoji) {
+            self.emojis.push(emoji);
+        }
+    }
+
+    fn push_keyword(&mut self, keyword: &str) {
+        let keyword = keyword.trim_matches(|c: char| c.is_ascii_punctuation());
+        if keyword.is_empty() {
+            return;
+        }
+        let keyword = keyword.to_lowercase();
+        let total_len: usize = self.keywords.iter().map(|k| k.chars().count()).sum();
+        if self.keywords.len() < MAX_KEYWORDS
+            && total_len + keyword.chars().count() <= MAX_KEYWORDS_LEN
+            && !self.keywords.contains(&keyword)
+        {
+            self.keywords.push(keyword);
+        }
+    }
+
+    /// 合并另一组元数据，保留已有的顺序
+    pub fn merge(&mut self, other: StickerMeta) {
+        for emoji in other.emojis {
+            self.push_emoji(emoji);
+        }
+        for keyword in other.keywords {
+            self.push_keyword(&keyword);
+        }
+    }
+
+    /// 从消息的说明文字中解析
+    pub fn from_message(msg: &Message) -> Self {
+        msg.caption().map(Self::parse).unwrap_or_default()
+    }
+
+    /// 上传贴纸时使用的表情列表，为空时使用默认表情
+    pub fn emoji_list(&self, default: &str) -> Vec<String> {
+        if self.emojis.is_empty() {
+            vec![default.to_string()]
+        } else {
+            self.emojis.clone()
+        }
+    }
+
+    /// 是否匹配搜索词：表情完全相同，或关键词包含搜索词
+    pub fn matches(&self, query: &str) -> bool {
+        let query = query.trim().trim_start_matches('#').to_lowercase();
+        if query.is_empty() {
+            return true;
+        }
+        // 忽略变体选择符，使 ❤ 与 ❤️ 视为相同
+        let without_variation = |s: &str| s.replace('\u{FE0F}', "");
+        let emoji_query = without_variation(&query);
+        self.emojis
+            .iter()
+            .any(|emoji| without_variation(emoji) == emoji_query)
+            || self.keywords.iter().any(|keyword| keyword.contains(&query))
+    }
+}
@@ -0,0 +1,467 @@
+use serde::{Deserialize, Serialize};
+
+use crate::cache::fnv1a;
+
+/// 去除背景的方式
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BackgroundMode {
+    /// 从图片边缘向内填充与边缘颜色相近的区域，适合纯色背景
+    Auto,
+    /// 去除所有与指定颜色相近的像素（色键）
+    Color([u8; 3]),
+}
+
+/// 背景去除设置
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BackgroundRemoval {
+    pub mode: BackgroundMode,
+    /// 颜色容差（RGB 欧氏距离）
+    pub tolerance: u8,
+    /// 边缘羽化的像素数
+    pub feather: u8,
+}
+
+impl BackgroundRemoval {
+    pub const DEFAULT_TOLERANCE: u8 = 48;
+    pub const DEFAULT_FEATHER: u8 = 1;
+    pub const MAX_FEATHER: u8 = 10;
+
+    pub fn new(mode: BackgroundMode) -> Self {
+        Self {
+            mode,
+            tolerance: Self::DEFAULT_TOLERANCE,
+            feather: Self::DEFAULT_FEATHER,
+        }
+    }
+}
+
+/// 裁剪透明或纯色边缘的设置
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Trim {
+    /// 裁剪后四周保留的留白，以 512 像素的贴纸为准
+    pub padding: u32,
+}
+
+impl Trim {
+    pub const DEFAULT_PADDING: u32 = 8;
+    pub const MAX_PADDING: u32 = 128;
+}
+
+/// 主体轮廓外的描边
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Outline {
+    /// 描边宽度，以 512 像素的贴纸为准
+    pub width: u32,
+    pub color: [u8; 3],
+}
+
+impl Outline {
+    pub const DEFAULT_WIDTH: u32 = 8;
+    pub const MAX_WIDTH: u32 = 32;
+    pub const DEFAULT_COLOR: [u8; 3] = [255, 255, 255];
+}
+
+/// 柔和的投影
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Shadow {
+    /// 向右下方的偏移，以 512 像素的贴纸为准
+    pub offset: u32,
+    /// 模糊半径（高斯模糊的 sigma），以 512 像素的贴纸为准
+    pub blur: u32,
+    /// 不透明度
+    pub opacity: u8,
+}
+
+impl Shadow {
+    pub const DEFAULT_OFFSET: u32 = 6;
+    pub const DEFAULT_BLUR: u32 = 6;
+    pub const MAX_BLUR: u32 = 24;
+    pub const DEFAULT_OPACITY: u8 = 128;
+
+    pub fn new(blur: u32) -> Self {
+        Self {
+            offset: Self::DEFAULT_OFFSET,
+            blur,
+            opacity: Self::DEFAULT_OPACITY,
+        }
+    }
+}
+
+/// 裁剪区域
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Crop {
+    /// 原图中的矩形区域（像素）
+    Rect {
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    },
+    /// 按宽高比居中裁剪出最大的区域
+    Aspect { width: u32, height: u32 },
+}
+
+/// 翻转方向
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Flip {
+    Horizontal,
+    Vertical,
+    Both,
+}
+
+/// 补成正方形时的填充
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SquareFill {
+    Transparent,
+    Color([u8; 3]),
+}
+
+/// 几何变换，图片和视频共用同一描述，依次执行裁剪、旋转、翻转，最后补成正方形
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Transform {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub crop: Option<Crop>,
+    /// 顺时针旋转的角度，范围 (0, 360)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rotate: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub flip: Option<Flip>,
+    /// 在缩放前补成正方形
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub square: Option<SquareFill>,
+}
+
+impl Transform {
+    pub fn is_identity(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// 把图片切成若干个自定义表情拼成的网格
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Grid {
+    pub columns: u32,
+    pub rows: u32,
+}
+
+impl Grid {
+    /// 每行、每列最多的表情数
+    pub const MAX_SIDE: u32 = 8;
+
+    /// 表情总数
+    pub fn count(&self) -> usize {
+        (self.columns * self.rows) as usize
+    }
+}
+
+/// 文字的位置
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TextPosition {
+    Top,
+    Center,
+    #[default]
+    Bottom,
+}
+
+impl std::str::FromStr for TextPosition {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "top" | "上" => Ok(Self::Top),
+            "center" | "middle" | "中" => Ok(Self::Center),
+            "bottom" | "下" => Ok(Self::Bottom),
+            _ => Err(()),
+        }
+    }
+}
+
+/// 叠加在贴纸上的文字
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TextOverlay {
+    pub text: String,
+    pub position: TextPosition,
+}
+
+impl TextOverlay {
+    pub const MAX_CHARS: usize = 64;
+}
+
+/// 处理选项，参与结果缓存的键
+///
+/// 可以在说明文字中以 `名称:值` 的形式指定，例如 `bg:auto tolerance:60 trim:on outline:8`。
+/// 文字叠加 `text:` 例外，它占据该行剩余的全部内容，例如 `text: 你好 / bottom`。
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ProcessOptions {
+    /// 去除背景（仅静态图片）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub background: Option<BackgroundRemoval>,
+    /// 缩放前裁剪到内容区域
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trim: Option<Trim>,
+    /// 主体描边（仅静态图片）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub outline: Option<Outline>,
+    /// 投影（仅静态图片）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shadow: Option<Shadow>,
+    /// 叠加的文字
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<TextOverlay>,
+    /// 切成自定义表情网格（仅自定义表情）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub grid: Option<Grid>,
+    /// 裁剪、旋转、翻转和补成正方形
+    #[serde(default, skip_serializing_if = "Transform::is_identity")]
+    pub transform: Transform,
+}
+
+/// 解析 `#RRGGBB` 或 `RRGGBB` 形式的颜色
+pub fn parse_color(value: &str) -> Option<[u8; 3]> {
+    let hex = value.trim_start_matches('#');
+    if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    Some([channel(0)?, channel(2)?, channel(4)?])
+}
+
+/// 解析裁剪设置：`on` 使用默认留白，数字表示留白像素数，`off` 表示不裁剪
+pub fn parse_trim(value: &str) -> Option<Option<Trim>> {
+    match value.to_lowercase().as_str() {
+        "off" | "none" | "no" => Some(None),
+        "on" | "auto" | "yes" => Some(Some(Trim {
+            padding: Trim::DEFAULT_PADDING,
+        })),
+        other => other
+            .parse::<u32>()
+            .ok()
+            .filter(|padding| *padding <= Trim::MAX_PADDING)
+            .map(|padding| Some(Trim { padding })),
+    }
+}
+
+/// 解析描边宽度：`on` 使用默认宽度，`off` 或 `0` 表示不描边
+pub fn parse_outline(value: &str) -> Option<Option<u32>> {
+    match value.to_lowercase().as_str() {
+        "off" | "none" | "no" | "0" => Some(None),
+        "on" | "yes" => Some(Some(Outline::DEFAULT_WIDTH)),
+        other => other
+            .parse::<u32>()
+            .ok()
+            .filter(|width| *width <= Outline::MAX_WIDTH)
+            .map(Some),
+    }
+}
+
+/// 解析投影：`on` 使用默认模糊半径，数字表示模糊半径，`off` 表示不加投影
+pub fn parse_shadow(value: &str) -> Option<Option<Shadow>> {
+    match value.to_lowercase().as_str() {
+        "off" | "none" | "no" => Some(None),
+        "on" | "yes" => Some(Some(Shadow::new(Shadow::DEFAULT_BLUR))),
+        other => other
+            .parse::<u32>()
+            .ok()
+            .filter(|blur| *blur <= Shadow::MAX_BLUR)
+            .map(|blur| Some(Shadow::new(blur))),
+    }
+}
+
+/// 解析裁剪区域：`宽x高+X+Y` 为像素矩形，`宽:高` 为宽高比，`off` 表示不裁剪
+pub fn parse_crop(value: &str) -> Option<Option<Crop>> {
+    let value = value.to_lowercase();
+    if matches!(value.as_str(), "off" | "none" | "no") {
+        return Some(None);
+    }
+    if let Some((width, height)) = value.split_once([':', '/']) {
+        let ratio = |side: &str| {
+            side.parse::<u32>()
+                .ok()
+                .filter(|side| (1..=100).contains(side))
+        };
+        return Some(Some(Crop::Aspect {
+            width: ratio(width)?,
+            height: ratio(height)?,
+        }));
+    }
+    let mut parts = value.split('+');
+    let (width, height) = parts.next()?.split_once('x')?;
+    let crop = Crop::Rect {
+        width: width.parse().ok().filter(|width| *width > 0)?,
+        height: height.parse().ok().filter(|height| *height > 0)?,
+        x: parts.next().map_or(Some(0), |x| x.parse().ok())?,
+        y: parts.next().map_or(Some(0), |y| y.parse().ok())?,
+    };
+    parts.next().is_none().then_some(Some(crop))
+}
+
+/// 解析旋转角度（顺时针，可为负数或小数），0 或 `off` 表示不旋转
+pub fn parse_rotate(value: &str) -> Option<Option<f32>> {
+    if matches!(value.to_lowercase().as_str(), "off" | "none" | "no") {
+        return Some(None);
+    }
+    let degrees = value
+        .trim_end_matches('°')
+        .parse::<f32>()
+        .ok()
+        .filter(|degrees| degrees.is_finite())?
+        .rem_euclid(360.0);
+    Some((degrees > 0.0).then_some(degrees))
+}
+
+/// 解析翻转方向：`h` 水平、`v` 垂直、`hv` 两者
+pub fn parse_flip(value: &str) -> Option<Option<Flip>> {
+    match value.to_lowercase().as_str() {
+        "off" | "none" | "no" => Some(None),
+        "h" | "horizontal" => Some(Some(Flip::Horizontal)),
+        "v" | "vertical" => Some(Some(Flip::Vertical)),
+        "hv" | "vh" | "both" => Some(Some(Flip::Both)),
+        _ => None,
+    }
+}
+
+/// 解析补成正方形的填充：`on` 透明，`#RRGGBB` 纯色，`off` 不补
+pub fn parse_square(value: &str) -> Option<Option<SquareFill>> {
+    match value.to_lowercase().as_str() {
+        "off" | "none" | "no" => Some(None),
+        "on" | "yes" | "transparent" => Some(Some(SquareFill::Transparent)),
+        other => parse_color(other).map(|color| Some(SquareFill::Color(color))),
+    }
+}
+
+/// 解析网格：`列x行`，例如 `3x3`，`off` 表示不切分
+pub fn parse_grid(value: &str) -> Option<Option<Grid>> {
+    let value = value.to_lowercase();
+    if matches!(value.as_str(), "off" | "none" | "no") {
+        return Some(None);
+    }
+    let (columns, rows) = value.split_once(['x', '*', '×'])?;
+    let grid = Grid {
+        columns: columns.parse().ok()?,
+        rows: rows.parse().ok()?,
+    };
+    let valid = |side: u32| (1..=Grid::MAX_SIDE).contains(&side);
+    (valid(grid.columns) && valid(grid.rows) && grid.count() > 1).then_some(Some(grid))
+}
+
+/// 解析文字叠加：`文字 / 位置`，位置可省略（默认在底部）
+pub fn parse_text_overlay(value: &str) -> Option<TextOverlay> {
+    let (text, position) = value
+        .rsplit_once('/')
+        .and_then(|(text, position)| Some((text, position.parse().ok()?)))
+        .unwrap_or((value, TextPosition::default()));
+    let text = text.trim();
+    if text.is_empty() || text.chars().count() > TextOverlay::MAX_CHARS {
+        return None;
+    }
+    Some(TextOverlay {
+        text: text.to_string(),
+        position,
+    })
+}
+
+/// 把一行拆成普通选项和 `text:` 之后的内容
+fn split_text_overlay(line: &str) -> (&str, Option<&str>) {
+    let start = line.char_indices().find(|&(i, _)| {
+        line.get(i..i + 5)
+            .is_some_and(|prefix| prefix.eq_ignore_ascii_case("text:"))
+            && line[..i]
+                .chars()
+                .next_back()
+                .is_none_or(char::is_whitespace)
+    });
+    match start {
+        Some((i, _)) => (&line[..i], Some(&line[i + 5..])),
+        None => (line, None),
+    }
+}
+
+/// 解析背景去除方式：`auto`、`#RRGGBB`，`off` 表示不去除
+pub fn parse_background(value: &str) -> Option<Option<BackgroundMode>> {
+    match value.to_lowercase().as_str() {
+        "off" | "none" | "no" => Some(None),
+        "auto" | "on" | "yes" => Some(Some(BackgroundMode::Auto)),
+        other => parse_color(other).map(|color| Some(BackgroundMode::Color(color))),
+    }
+}
+
+impl ProcessOptions {
+    /// 稳定的选项哈希（FNV-1a），跨重启保持一致以便持久化缓存
+    pub fn hash(&self) -> u64 {
+        fnv1a(serde_json::to_string(self).unwrap_or_default().as_bytes())
+    }
+
+    /// 应用文本中的 `名称:值` 选项，覆盖已有的设置；不认识的词忽略
+    ///
+    /// 选项的值无效时返回该词。
+    pub fn apply_text(&mut self, text: &str) -> Result<(), String> {
+        let mut tolerance = None;
+        let mut feather = None;
+        let mut outline_color = None;
+        let mut words = Vec::new();
+        for line in text.lines() {
+            let (line, overlay) = split_text_overlay(line);
+            words.extend(line.split_whitespace());
+            if let Some(overlay) = overlay {
+                self.text =
+                    Some(parse_text_overlay(overlay).ok_or_else(|| format!("text:{}", overlay))?);
+            }
+        }
+        for word in words {
+            let Some((name, value)) = word.split_once(':') else {
+                continue;
+            };
+            let invalid = || word.to_string();
+            match name.to_lowercase().as_str() {
+                "bg" => {
+                    let mode = parse_background(value).ok_or_else(invalid)?;
+                    self.background = mode.map(BackgroundRemoval::new);
+                }
+                "trim" => self.trim = parse_trim(value).ok_or_else(invalid)?,
+                "tolerance" => tolerance = Some(value.parse::<u8>().map_err(|_| invalid())?),
+                "feather" => {
+                    feather = Some(
+                        value
+                            .parse::<u8>()
+                            .ok()
+                            .filter(|f| *f <= BackgroundRemoval::MAX_FEATHER)
+                            .ok_or_else(invalid)?,
+                    )
+                }
+                "outline" => {
+                    // 只改宽度时保留已有的描边颜色
+                    let color = self.outline.map_or(Outline::DEFAULT_COLOR, |o| o.color);
+                    self.outline = parse_outline(value)
+                        .ok_or_else(invalid)?
+                        .map(|width| Outline { width, color });
+                }
+                "outlinecolor" => outline_color = Some(parse_color(value).ok_or_else(invalid)?),
+                "shadow" => self.shadow = parse_shadow(value).ok_or_else(invalid)?,
+                "grid" => self.grid = parse_grid(value).ok_or_else(invalid)?,
+                "crop" => self.transform.crop = parse_crop(value).ok_or_else(invalid)?,
+                "rotate" => self.transform.rotate = parse_rotate(value).ok_or_else(invalid)?,
+                "flip" => self.transform.flip = parse_flip(value).ok_or_else(invalid)?,
+                "square" => self.transform.square = parse_square(value).ok_or_else(invalid)?,
+                _ => {}
+            }
+        }
+        // 容差、羽化和描边颜色可以写在对应选项之前，最后统一应用
+        if let Some(background) = self.background.as_mut() {
+            if let Some(tolerance) = tolerance {
+                background.tolerance = tolerance;
+            }
+            if let Some(feather) = feather {
+                background.feather = feather;
+            }
+        }
+        if let (Some(outline), Some(color)) = (self.outline.as_mut(), outline_color) {
+            outline.color = color;
+        }
+        Ok(())
+    }
+}
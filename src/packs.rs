@@ -0,0 +1,998 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use teloxide::ApiError;
+use teloxide::prelude::*;
+use teloxide::types::{InputFile, InputSticker, Me, Sticker, StickerSet, StickerType, UserId};
+use teloxide::utils::command::BotCommands;
+
+use crate::auth::SharedAuth;
+use crate::encode::EncoderConfig;
+use crate::export::{export_pack, parse_set_name};
+use crate::grid::convert_tiles;
+use crate::handlers::{
+    MediaJob, download_and_convert, limited_user, reject_if_limited, replied_media_or_reply,
+};
+use crate::i18n::Lang;
+use crate::limits::{SharedLimiter, UserQuota};
+use crate::media::{Converted, Failure, Target, detect_type, download_file, error_message};
+use crate::metadata::StickerMeta;
+use crate::options::{Grid, parse_grid};
+use crate::reply::ReplyToExt;
+use crate::retry::RetryAfterExt;
+use crate::storage::{JsonWriter, load_json, to_json};
+use crate::tr;
+
+const PACKS_FILE: &str = "packs.json";
+
+#[derive(BotCommands, Clone)]
+#[command(rename_rule = "lowercase", description = "贴纸包命令：")]
+pub enum PackCommand {
+    #[command(description = "回复一条媒体消息，创建贴纸包：/newpack <名称> <标题>")]
+    NewPack(String),
+    #[command(description = "回复一条媒体消息，添加到贴纸包：/addsticker [名称] [表情] [#关键词]")]
+    AddSticker(String),
+    #[command(description = "回复贴纸，将其从贴纸包删除")]
+    RemoveSticker,
+    #[command(description = "回复贴纸，移动到指定位置：/movesticker <位置>")]
+    MoveSticker(String),
+    #[command(
+        description = "回复一条媒体消息，切分为自定义表情网格并添加到表情包：/addgrid <列x行> [名称] [标题]"
+    )]
+    AddGrid(String),
+    #[command(description = "回复一条媒体消息，设为贴纸包图标：/setpackicon [名称]")]
+    SetPackIcon(String),
+    #[command(description = "查看你的贴纸包")]
+    Packs,
+    #[command(description = "复制贴纸包：/clonepack <新名称> <源贴纸包> [更多源贴纸包...]")]
+    ClonePack(String),
+    #[command(description = "导出整个贴纸包为压缩包：回复贴纸或 /exportpack <名称或链接>")]
+    ExportPack(String),
+}
+
+/// 未指定表情时使用的默认表情
+pub const DEFAULT_EMOJI: &str = "🙂";
+
+/// 用户通过机器人创建的贴纸包
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct UserPack {
+    /// 完整的贴纸包名称（以 `_by_<机器人用户名>` 结尾）
+    pub name: String,
+    pub title: String,
+    /// 是否为自定义表情包
+    #[serde(default)]
+    pub custom_emoji: bool,
+}
+
+/// 每个用户的贴纸包记录
+pub struct PackStore {
+    packs: Mutex<HashMap<UserId, Vec<UserPack>>>,
+    writer: JsonWriter,
+}
+
+pub type SharedPacks = Arc<PackStore>;
+
+impl PackStore {
+    /// 从数据目录加载贴纸包记录
+    pub fn load() -> Result<Self> {
+        let packs = load_json(PACKS_FILE)?.unwrap_or_default();
+        Ok(Self {
+            packs: Mutex::new(packs),
+            writer: JsonWriter::new(PACKS_FILE),
+        })
+    }
+
+    /// 记录新创建的贴纸包
+    pub async fn add(&self, user_id: UserId, pack: UserPack) -> Result<()> {
+        {
+            let mut packs = self.packs.lock().unwrap();
+            let user_packs = packs.entry(user_id).or_default();
+            user_packs.retain(|p| p.name != pack.name);
+            user_packs.push(pack);
+        }
+        self.writer
+            .save(|| to_json(&*self.packs.lock().unwrap()))
+            .await
+    }
+
+    /// 用户的所有贴纸包，按创建顺序排列
+    pub fn list(&self, user_id: UserId) -> Vec<UserPack> {
+        self.packs
+            .lock()
+            .unwrap()
+            .get(&user_id)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// 查找用户的贴纸包；未指定名称时返回最近创建的贴纸包
+    pub fn find(&self, user_id: UserId, name: Option<&str>, me: &Me) -> Option<UserPack> {
+        let packs = self.list(user_id);
+        match name {
+            Some(name) => {
+                let full_name = full_set_name(name, me, Lang::default()).ok()?;
+                packs.into_iter().find(|p| p.name == full_name)
+            }
+            None => packs.into_iter().last(),
+        }
+    }
+
+    /// 用户是否拥有该贴纸包
+    pub fn owns(&self, user_id: UserId, name: &str) -> bool {
+        self.list(user_id).iter().any(|p| p.name == name)
+    }
+}
+
+/// 生成完整的贴纸包名称，必要时追加 `_by_<机器人用户名>` 后缀
+///
+/// Telegram 要求名称只包含英文字母、数字和下划线，以字母开头，不能有连续的下划线，且不超过 64 个字符。
+pub fn full_set_name(name: &str, me: &Me, lang: Lang) -> Result<String> {
+    let suffix = format!("_by_{}", me.username());
+    let name = name.trim();
+    let full_name = if name.to_lowercase().ends_with(&suffix.to_lowercase()) {
+        name.to_string()
+    } else {
+        format!("{}{}", name, suffix)
+    };
+
+    let valid = full_name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && full_name.starts_with(|c: char| c.is_ascii_alphabetic())
+        && !full_name.contains("__")
+        && full_name.len() <= 64;
+    if !valid {
+        return Err(Failure::Localized(tr!(
+            lang,
+            PackInvalidName,
+            name,
+            64usize.saturating_sub(suffix.len())
+        ))
+        .into());
+    }
+    Ok(full_name)
+}
+
+/// 贴纸包的分享链接
+pub fn pack_link(name: &str) -> String {
+    format!("https://t.me/addstickers/{}", name)
+}
+
+/// 将转换结果包装为上传用的 InputSticker，未指定表情时使用默认表情
+pub fn input_sticker(converted: &Converted, meta: &StickerMeta) -> Result<InputSticker> {
+    let format = converted
+        .sticker_format()
+        .ok_or_else(|| anyhow!("处理结果不是贴纸"))?;
+    Ok(InputSticker {
+        sticker: InputFile::file(&converted.path),
+        format,
+        emoji_list: meta.emoji_list(DEFAULT_EMOJI),
+        mask_position: None,
+        keywords: meta.keywords.clone(),
+    })
+}
+
+/// 回复文本消息
+async fn reply_text(bot: &Bot, msg: &Message, text: impl Into<String>) -> Result<()> {
+    bot.send_message(msg.chat.id, text)
+        .reply_to(msg)
+        .send_retry()
+        .await?;
+    Ok(())
+}
+
+/// 取出命令所回复的媒体并检查限流和配额，返回处理任务及需要计入配额的用户
+///
+/// 说明文字中的处理选项同样生效。未回复媒体、选项无效或被限流时已回复提示，返回 None。
+async fn replied_job(
+    bot: &Bot,
+    msg: &Message,
+    target: Target,
+    auth: &SharedAuth,
+    limiter: &SharedLimiter,
+    lang: Lang,
+) -> Result<Option<(MediaJob, Option<UserId>)>> {
+    let Some((replied, file)) = replied_media_or_reply(bot, msg, lang).await? else {
+        return Ok(None);
+    };
+    let job = match MediaJob::new(file, target, replied, Default::default(), lang) {
+        Ok(job) => job,
+        Err(text) => {
+            reply_text(bot, msg, text).await?;
+            return Ok(None);
+        }
+    };
+    let user_id = limited_user(msg, auth);
+    if reject_if_limited(bot, msg, user_id, job.file.size as u64, limiter, lang).await? {
+        return Ok(None);
+    }
+    Ok(Some((job, user_id)))
+}
+
+/// 下载并转换命令所回复的媒体，受限流和配额限制，同时返回其说明文字中的表情和关键词
+///
+/// 说明文字中的处理选项同样生效。未回复媒体、选项无效、被限流或转换失败时已回复提示，返回 None。
+async fn convert_replied(
+    bot: &Bot,
+    msg: &Message,
+    target: Target,
+    auth: &SharedAuth,
+    limiter: &SharedLimiter,
+    encoder: &EncoderConfig,
+    lang: Lang,
+) -> Result<Option<(Converted, StickerMeta)>> {
+    let Some((job, user_id)) = replied_job(bot, msg, target, auth, limiter, lang).await? else {
+        return Ok(None);
+    };
+    let quota = user_id.map(|user_id| UserQuota { limiter, user_id });
+    match download_and_convert(bot, job.file, target, &job.options, encoder, lang, quota).await {
+        Ok((converted, input_size)) => {
+            if let Some(user_id) = user_id {
+                limiter
+                    .record_batch(user_id, converted.conversions, input_size)
+                    .await?;
+            }
+            Ok(Some((converted, job.meta)))
+        }
+        Err(e) => {
+            log::error!("贴纸包文件处理失败: {:?}", e);
+            let text = tr!(lang, ProcessingFailed, error_message(&e, lang));
+            reply_text(bot, msg, text).await?;
+            Ok(None)
+        }
+    }
+}
+
+/// 下载命令所回复的媒体并切分为自定义表情网格，逐行从左到右排列
+///
+/// 与 [`convert_replied`] 相同，失败时已回复提示，返回 None。
+async fn convert_replied_grid(
+    bot: &Bot,
+    msg: &Message,
+    grid: &Grid,
+    auth: &SharedAuth,
+    limiter: &SharedLimiter,
+    encoder: &EncoderConfig,
+    lang: Lang,
+) -> Result<Option<(Vec<Converted>, StickerMeta)>> {
+    let Some((job, user_id)) = replied_job(bot, msg, Target::Emoji, auth, limiter, lang).await?
+    else {
+        return Ok(None);
+    };
+    let result = async {
+        let (input, input_size) = download_file(bot, job.file.id.clone()).await?;
+        let detected = detect_type(input.path())?;
+        let tiles =
+            convert_tiles(input.path(), &detected, grid, &job.options, encoder, lang).await?;
+        anyhow::Ok((tiles, input_size))
+    }
+    .await;
+    match result {
+        Ok((tiles, input_size)) => {
+            if let Some(user_id) = user_id {
+                limiter.record(user_id, input_size).await?;
+            }
+            Ok(Some((tiles, job.meta)))
+        }
+        Err(e) => {
+            log::error!("表情网格处理失败: {:?}", e);
+            let text = tr!(lang, ProcessingFailed, error_message(&e, lang));
+            reply_text(bot, msg, text).await?;
+            Ok(None)
+        }
+    }
+}
+
+/// 报告 Telegram 请求的结果
+async fn reply_result(
+    bot: &Bot,
+    msg: &Message,
+    result: Result<(), teloxide::RequestError>,
+    success: String,
+    lang: Lang,
+) -> Result<()> {
+    match result {
+        Ok(()) => reply_text(bot, msg, success).await,
+        Err(e) => {
+            log::warn!("ChatID: {}, 贴纸包操作失败: {}", msg.chat.id, e);
+            reply_text(bot, msg, tr!(lang, PackOperationFailed, e)).await
+        }
+    }
+}
+
+/// /newpack <名称> <标题>：用被回复的媒体创建新贴纸包
+#[allow(clippy::too_many_arguments)]
+async fn new_pack(
+    bot: &Bot,
+    msg: &Message,
+    args: &str,
+    me: &Me,
+    packs: &SharedPacks,
+    auth: &SharedAuth,
+    limiter: &SharedLimiter,
+    encoder: &EncoderConfig,
+    lang: Lang,
+) -> Result<()> {
+    let Some(user) = msg.from.as_ref() else {
+        return Ok(());
+    };
+    let Some((name, title)) = args
+        .trim()
+        .split_once(char::is_whitespace)
+        .map(|(name, title)| (name, title.trim()))
+        .filter(|(_, title)| !title.is_empty())
+    else {
+        return reply_text(bot, msg, tr!(lang, NewPackUsage)).await;
+    };
+    let name = match full_set_name(name, me, lang) {
+        Ok(name) => name,
+        Err(e) => return reply_text(bot, msg, e.to_string()).await,
+    };
+    if title.chars().count() > 64 {
+        return reply_text(bot, msg, tr!(lang, PackTitleTooLong)).await;
+    }
+
+    let Some((converted, meta)) =
+        convert_replied(bot, msg, Target::Sticker, auth, limiter, encoder, lang).await?
+    else {
+        return Ok(());
+    };
+    let sticker = input_sticker(&converted, &meta)?;
+    let result = bot
+        .create_new_sticker_set(user.id, &name, title, vec![sticker])
+        .sticker_type(StickerType::Regular)
+        .send_retry()
+        .await
+        .map(|_| ());
+    if result.is_ok() {
+        packs
+            .add(
+                user.id,
+                UserPack {
+                    name: name.clone(),
+                    title: title.to_string(),
+                    custom_emoji: false,
+                },
+            )
+            .await?;
+        log::info!("用户 {} 创建了贴纸包 {}", user.id, name);
+    }
+    reply_result(
+        bot,
+        msg,
+        result,
+        tr!(lang, PackCreated, title, pack_link(&name)),
+        lang,
+    )
+    .await
+}
+
+/// /addsticker [名称]：将被回复的媒体添加到贴纸包，未指定名称时使用最近创建的贴纸包
+#[allow(clippy::too_many_arguments)]
+async fn add_sticker(
+    bot: &Bot,
+    msg: &Message,
+    args: &str,
+    me: &Me,
+    packs: &SharedPacks,
+    auth: &SharedAuth,
+    limiter: &SharedLimiter,
+    encoder: &EncoderConfig,
+    lang: Lang,
+) -> Result<()> {
+    let Some(user) = msg.from.as_ref() else {
+        return Ok(());
+    };
+    // 命令参数中的表情和关键词优先于被回复消息的说明文字
+    let (name, mut meta) = StickerMeta::extract(args);
+    let name = Some(name.as_str()).filter(|name| !name.is_empty());
+    let Some(pack) = packs.find(user.id, name, me) else {
+        return reply_text(bot, msg, tr!(lang, PackNotFoundCreate)).await;
+    };
+    let target = if pack.custom_emoji {
+        Target::Emoji
+    } else {
+        Target::Sticker
+    };
+
+    let Some((converted, caption_meta)) =
+        convert_replied(bot, msg, target, auth, limiter, encoder, lang).await?
+    else {
+        return Ok(());
+    };
+    meta.merge(caption_meta);
+    let sticker = input_sticker(&converted, &meta)?;
+    let result = bot
+        .add_sticker_to_set(user.id, &pack.name, sticker)
+        .send_retry()
+        .await
+        .map(|_| ());
+    reply_result(
+        bot,
+        msg,
+        result,
+        tr!(lang, StickerAdded, pack.title, pack_link(&pack.name)),
+        lang,
+    )
+    .await
+}
+
+/// 创建贴纸包时最多附带的初始贴纸数（Telegram 限制）
+const MAX_INITIAL_STICKERS: usize = 50;
+
+/// /addgrid <列x行> [名称] [标题]：将被回复的媒体切分为自定义表情网格，按行依次添加到自定义表情包
+///
+/// 未指定名称时使用最近创建的自定义表情包；指定的表情包不存在且给出了标题时新建。
+#[allow(clippy::too_many_arguments)]
+async fn add_grid(
+    bot: &Bot,
+    msg: &Message,
+    args: &str,
+    me: &Me,
+    packs: &SharedPacks,
+    auth: &SharedAuth,
+    limiter: &SharedLimiter,
+    encoder: &EncoderConfig,
+    lang: Lang,
+) -> Result<()> {
+    let Some(user) = msg.from.as_ref() else {
+        return Ok(());
+    };
+    let mut words = args.split_whitespace();
+    let Some(Some(grid)) = words.next().and_then(parse_grid) else {
+        return reply_text(bot, msg, tr!(lang, AddGridUsage, Grid::MAX_SIDE)).await;
+    };
+    let name = words.next();
+    let title = words.collect::<Vec<_>>().join(" ");
+
+    let existing = match name {
+        Some(name) => packs.find(user.id, Some(name), me),
+        None => packs
+            .list(user.id)
+            .into_iter()
+            .rfind(|pack| pack.custom_emoji),
+    };
+    let (pack, exists) = match (existing, name) {
+        (Some(pack), _) if !pack.custom_emoji => {
+            return reply_text(bot, msg, tr!(lang, NotEmojiPack, pack.title)).await;
+        }
+        (Some(pack), _) => (pack, true),
+        (None, Some(name)) if !title.is_empty() => {
+            let name = match full_set_name(name, me, lang) {
+                Ok(name) => name,
+                Err(e) => return reply_text(bot, msg, e.to_string()).await,
+            };
+            if title.chars().count() > 64 {
+                return reply_text(bot, msg, tr!(lang, PackTitleTooLong)).await;
+            }
+            let pack = UserPack {
+                name,
+                title,
+                custom_emoji: true,
+            };
+            (pack, false)
+        }
+        _ => return reply_text(bot, msg, tr!(lang, AddGridUsage, Grid::MAX_SIDE)).await,
+    };
+
+    let Some((tiles, meta)) =
+        convert_replied_grid(bot, msg, &grid, auth, limiter, encoder, lang).await?
+    else {
+        return Ok(());
+    };
+    let mut stickers = tiles
+        .iter()
+        .map(|tile| input_sticker(tile, &meta))
+        .collect::<Result<Vec<_>>>()?;
+
+    // 新建时首批贴纸随创建请求上传，其余逐个按顺序添加
+    let result = async {
+        if !exists {
+            let rest = stickers.split_off(stickers.len().min(MAX_INITIAL_STICKERS));
+            bot.create_new_sticker_set(user.id, &pack.name, &pack.title, stickers)
+                .sticker_type(StickerType::CustomEmoji)
+                .send_retry()
+                .await?;
+            packs.add(user.id, pack.clone()).await?;
+            log::info!("用户 {} 创建了自定义表情包 {}", user.id, pack.name);
+            stickers = rest;
+        }
+        for sticker in stickers {
+            bot.add_sticker_to_set(user.id, &pack.name, sticker)
+                .send_retry()
+                .await?;
+        }
+        anyhow::Ok(())
+    }
+    .await;
+    let text = match result {
+        Ok(()) => tr!(
+            lang,
+            GridAdded,
+            grid.columns,
+            grid.rows,
+            pack.title,
+            pack_link(&pack.name)
+        ),
+        Err(e) => {
+            log::warn!("ChatID: {}, 表情网格添加失败: {:?}", msg.chat.id, e);
+            tr!(lang, PackOperationFailed, error_message(&e, lang))
+        }
+    };
+    reply_text(bot, msg, text).await
+}
+
+/// 获取被回复的贴纸，并确认它属于用户通过机器人创建的贴纸包
+///
+/// 不满足条件时已回复提示，返回 None。
+async fn owned_replied_sticker(
+    bot: &Bot,
+    msg: &Message,
+    packs: &SharedPacks,
+    lang: Lang,
+) -> Result<Option<(String, String)>> {
+    let Some(user) = msg.from.as_ref() else {
+        return Ok(None);
+    };
+    let Some(sticker) = msg.reply_to_message().and_then(|reply| reply.sticker()) else {
+        reply_text(bot, msg, tr!(lang, ReplyToPackSticker)).await?;
+        return Ok(None);
+    };
+    match &sticker.set_name {
+        Some(set_name) if packs.owns(user.id, set_name) => {
+            Ok(Some((sticker.file.id.to_string(), set_name.clone())))
+        }
+        _ => {
+            reply_text(bot, msg, tr!(lang, PackNotOwned)).await?;
+            Ok(None)
+        }
+    }
+}
+
+/// /removesticker：从贴纸包中删除被回复的贴纸
+async fn remove_sticker(bot: &Bot, msg: &Message, packs: &SharedPacks, lang: Lang) -> Result<()> {
+    let Some((file_id, set_name)) = owned_replied_sticker(bot, msg, packs, lang).await? else {
+        return Ok(());
+    };
+    let result = bot
+        .delete_sticker_from_set(file_id)
+        .send_retry()
+        .await
+        .map(|_| ());
+    reply_result(
+        bot,
+        msg,
+        result,
+        tr!(lang, StickerRemoved, pack_link(&set_name)),
+        lang,
+    )
+    .await
+}
+
+/// /movesticker <位置>：移动被回复的贴纸到指定位置（从 1 开始）
+async fn move_sticker(
+    bot: &Bot,
+    msg: &Message,
+    args: &str,
+    packs: &SharedPacks,
+    lang: Lang,
+) -> Result<()> {
+    let Some(position) = args.trim().parse::<u32>().ok().filter(|p| *p >= 1) else {
+        return reply_text(bot, msg, tr!(lang, MoveStickerUsage)).await;
+    };
+    let Some((file_id, set_name)) = owned_replied_sticker(bot, msg, packs, lang).await? else {
+        return Ok(());
+    };
+    let result = bot
+        .set_sticker_position_in_set(file_id, position - 1)
+        .send_retry()
+        .await
+        .map(|_| ());
+    reply_result(
+        bot,
+        msg,
+        result,
+        tr!(lang, StickerMoved, position, pack_link(&set_name)),
+        lang,
+    )
+    .await
+}
+
+/// /setpackicon [名称]：将被回复的媒体设为贴纸包图标（100x100）
+///
+/// 自定义表情包的图标只能是包中的表情，需要回复包中的一个表情。
+#[allow(clippy::too_many_arguments)]
+async fn set_pack_icon(
+    bot: &Bot,
+    msg: &Message,
+    args: &str,
+    me: &Me,
+    packs: &SharedPacks,
+    auth: &SharedAuth,
+    limiter: &SharedLimiter,
+    encoder: &EncoderConfig,
+    lang: Lang,
+) -> Result<()> {
+    let Some(user) = msg.from.as_ref() else {
+        return Ok(());
+    };
+    let name = Some(args.trim()).filter(|name| !name.is_empty());
+    let Some(pack) = packs.find(user.id, name, me) else {
+        return reply_text(bot, msg, tr!(lang, PackNotFound)).await;
+    };
+
+    if pack.custom_emoji {
+        let emoji_id = msg
+            .reply_to_message()
+            .and_then(|reply| reply.sticker())
+            .filter(|sticker| sticker.set_name.as_deref() == Some(pack.name.as_str()))
+            .and_then(|sticker| sticker.kind.custom_emoji_id().cloned());
+        let Some(emoji_id) = emoji_id else {
+            return reply_text(bot, msg, tr!(lang, EmojiPackIconUsage, pack.title)).await;
+        };
+        let result = bot
+            .set_custom_emoji_sticker_set_thumbnail(&pack.name)
+            .custom_emoji_id(emoji_id)
+            .send_retry()
+            .await
+            .map(|_| ());
+        return reply_result(bot, msg, result, tr!(lang, PackIconSet, pack.title), lang).await;
+    }
+
+    let Some((converted, _)) =
+        convert_replied(bot, msg, Target::Thumbnail, auth, limiter, encoder, lang).await?
+    else {
+        return Ok(());
+    };
+    let format = converted
+        .sticker_format()
+        .ok_or_else(|| anyhow!("处理结果不是贴纸"))?;
+    let result = bot
+        .set_sticker_set_thumbnail(&pack.name, user.id, format)
+        .thumbnail(InputFile::file(&converted.path))
+        .send_retry()
+        .await
+        .map(|_| ());
+    reply_result(bot, msg, result, tr!(lang, PackIconSet, pack.title), lang).await
+}
+
+/// /packs：列出用户通过机器人创建的贴纸包
+async fn list_packs(bot: &Bot, msg: &Message, packs: &SharedPacks, lang: Lang) -> Result<()> {
+    let Some(user) = msg.from.as_ref() else {
+        return Ok(());
+    };
+    let user_packs = packs.list(user.id);
+    if user_packs.is_empty() {
+        return reply_text(bot, msg, tr!(lang, NoPacks)).await;
+    }
+    let lines: Vec<String> = user_packs
+        .iter()
+        .map(|pack| format!("- {}: {}", pack.title, pack_link(&pack.name)))
+        .collect();
+    reply_text(bot, msg, tr!(lang, PackList, lines.join("\n"))).await
+}
+
+/// 普通贴纸包最多容纳的贴纸数
+const MAX_SET_STICKERS: usize = 120;
+/// 每复制多少个贴纸更新一次进度
+const CLONE_PROGRESS_STEP: usize = 10;
+
+/// 准备上传的贴纸，持有重新编码的临时文件直到上传完成
+struct PreparedSticker {
+    input: InputSticker,
+    _converted: Option<Converted>,
+}
+
+/// 直接引用源贴纸的 file_id，无需重新上传
+///
+/// Bot API 的 Sticker 不包含搜索关键词，复制的贴纸只能保留表情。
+fn reuse_sticker(sticker: &Sticker) -> PreparedSticker {
+    PreparedSticker {
+        input: InputSticker {
+            sticker: InputFile::file_id(sticker.file.id.clone()),
+            format: sticker.format(),
+            emoji_list: vec![sticker.emoji.clone().unwrap_or(DEFAULT_EMOJI.to_string())],
+            mask_position: None,
+            keywords: Vec::new(),
+        },
+        _converted: None,
+    }
+}
+
+/// 下载源贴纸并用 process_image / process_webm 重新编码为符合规格的贴纸
+async fn reencode_sticker(
+    bot: &Bot,
+    sticker: &Sticker,
+    encoder: &EncoderConfig,
+    lang: Lang,
+) -> Result<PreparedSticker> {
+    let (converted, _) = download_and_convert(
+        bot,
+        sticker.file.clone(),
+        Target::Sticker,
+        &Default::default(),
+        encoder,
+        lang,
+        None,
+    )
+    .await?;
+    let meta = StickerMeta {
+        emojis: sticker.emoji.iter().cloned().collect(),
+        keywords: Vec::new(),
+    };
+    Ok(PreparedSticker {
+        input: input_sticker(&converted, &meta)?,
+        _converted: Some(converted),
+    })
+}
+
+/// 创建贴纸包（首个贴纸）或向已创建的贴纸包添加贴纸
+async fn upload_sticker(
+    bot: &Bot,
+    user_id: UserId,
+    name: &str,
+    title: &str,
+    created: bool,
+    sticker: InputSticker,
+) -> Result<(), teloxide::RequestError> {
+    if created {
+        bot.add_sticker_to_set(user_id, name, sticker)
+            .send_retry()
+            .await?;
+    } else {
+        bot.create_new_sticker_set(user_id, name, title, vec![sticker])
+            .sticker_type(StickerType::Regular)
+            .send_retry()
+            .await?;
+    }
+    Ok(())
+}
+
+/// 复制单个贴纸：普通静态或视频贴纸优先直接引用，失败或不符合规格时重新编码
+///
+/// TGS 动画贴纸无法重新编码，只能直接引用。返回重新编码时下载的字节数，直接引用时为 0。
+#[allow(clippy::too_many_arguments)]
+async fn clone_sticker(
+    bot: &Bot,
+    user_id: UserId,
+    name: &str,
+    title: &str,
+    created: bool,
+    sticker: &Sticker,
+    encoder: &EncoderConfig,
+    lang: Lang,
+) -> Result<u64> {
+    let can_reencode = !sticker.is_animated();
+    if sticker.is_regular() || !can_reencode {
+        let prepared = reuse_sticker(sticker);
+        match upload_sticker(bot, user_id, name, title, created, prepared.input).await {
+            Ok(()) => return Ok(0),
+            Err(e) if !can_reencode => return Err(e.into()),
+            Err(e @ teloxide::RequestError::Api(ApiError::StickerSetNameOccupied)) => {
+                return Err(e.into());
+            }
+            Err(e) => log::debug!(
+                "贴纸 {} 无法直接引用，重新编码: {}",
+                sticker.file.unique_id,
+                e
+            ),
+        }
+    }
+    let prepared = reencode_sticker(bot, sticker, encoder, lang).await?;
+    upload_sticker(bot, user_id, name, title, created, prepared.input).await?;
+    Ok(sticker.file.size as u64)
+}
+
+/// /clonepack <新名称> <源贴纸包...>：将一个或多个公开贴纸包复制为用户自己的新贴纸包
+#[allow(clippy::too_many_arguments)]
+async fn clone_pack(
+    bot: &Bot,
+    msg: &Message,
+    args: &str,
+    me: &Me,
+    packs: &SharedPacks,
+    auth: &SharedAuth,
+    limiter: &SharedLimiter,
+    encoder: &EncoderConfig,
+    lang: Lang,
+) -> Result<()> {
+    let Some(user) = msg.from.as_ref() else {
+        return Ok(());
+    };
+    let mut words = args.split_whitespace();
+    let name = words.next();
+    let sources: Vec<&str> = words.collect();
+    let Some(name) = name.filter(|_| !sources.is_empty()) else {
+        return reply_text(bot, msg, tr!(lang, ClonePackUsage)).await;
+    };
+    let name = match full_set_name(name, me, lang) {
+        Ok(name) => name,
+        Err(e) => return reply_text(bot, msg, e.to_string()).await,
+    };
+
+    // 获取所有源贴纸包
+    let invalid: Vec<&str> = sources
+        .iter()
+        .copied()
+        .filter(|source| parse_set_name(source).is_none())
+        .collect();
+    if !invalid.is_empty() {
+        return reply_text(bot, msg, tr!(lang, InvalidSetNames, invalid.join(", "))).await;
+    }
+    let mut source_sets: Vec<StickerSet> = Vec::with_capacity(sources.len());
+    for source_name in sources.into_iter().filter_map(parse_set_name) {
+        match bot.get_sticker_set(&source_name).send_retry().await {
+            Ok(set) => source_sets.push(set),
+            Err(e) => {
+                log::warn!("获取贴纸包 {} 失败: {}", source_name, e);
+                return reply_text(bot, msg, tr!(lang, SetNotFound, source_name)).await;
+            }
+        }
+    }
+    let title: String = source_sets
+        .iter()
+        .map(|set| set.title.as_str())
+        .collect::<Vec<_>>()
+        .join(" + ")
+        .chars()
+        .take(64)
+        .collect();
+    let mut stickers: Vec<&Sticker> = source_sets.iter().flat_map(|set| &set.stickers).collect();
+    let skipped = stickers.len().saturating_sub(MAX_SET_STICKERS);
+    stickers.truncate(MAX_SET_STICKERS);
+
+    // 直接引用的贴纸不下载，只有一定要重新编码的贴纸计入流量
+    let reencode_size: u64 = stickers
+        .iter()
+        .filter(|s| !s.is_regular() && !s.is_animated())
+        .map(|s| s.file.size as u64)
+        .sum();
+    let user_id = limited_user(msg, auth);
+    if reject_if_limited(bot, msg, user_id, reencode_size, limiter, lang).await? {
+        return Ok(());
+    }
+
+    let progress = bot
+        .send_message(
+            msg.chat.id,
+            tr!(lang, CloneProgress, title, 0, stickers.len()),
+        )
+        .reply_to(msg)
+        .send_retry()
+        .await?;
+
+    let mut created = false;
+    let mut failed = 0;
+    let mut downloaded = 0;
+    let mut last_error = None;
+    for (index, sticker) in stickers.iter().enumerate() {
+        match clone_sticker(bot, user.id, &name, &title, created, sticker, encoder, lang).await {
+            Ok(bytes) => {
+                downloaded += bytes;
+                if !created {
+                    created = true;
+                    packs
+                        .add(
+                            user.id,
+                            UserPack {
+                                name: name.clone(),
+                                title: title.clone(),
+                                custom_emoji: false,
+                            },
+                        )
+                        .await?;
+                }
+            }
+            Err(e) => {
+                log::warn!("复制贴纸 {} 失败: {:?}", sticker.file.unique_id, e);
+                failed += 1;
+                let name_occupied = matches!(
+                    e.downcast_ref::<teloxide::RequestError>(),
+                    Some(teloxide::RequestError::Api(
+                        ApiError::StickerSetNameOccupied
+                    ))
+                );
+                last_error = Some(e);
+                if !created && name_occupied {
+                    break;
+                }
+            }
+        }
+
+        let position = index + 1;
+        if position % CLONE_PROGRESS_STEP == 0 && position < stickers.len() {
+            let text = tr!(lang, CloneProgress, title, position, stickers.len());
+            if let Err(e) = bot
+                .edit_message_text(progress.chat.id, progress.id, text)
+                .send_retry()
+                .await
+            {
+                log::warn!("更新复制进度失败: {}", e);
+            }
+        }
+    }
+    if let Some(user_id) = user_id {
+        limiter.record(user_id, downloaded).await?;
+    }
+
+    let text = if created {
+        log::info!("用户 {} 复制贴纸包到 {}", user.id, name);
+        let mut text = tr!(
+            lang,
+            CloneDone,
+            title,
+            stickers.len() - failed,
+            failed,
+            pack_link(&name)
+        );
+        if skipped > 0 {
+            text.push_str(&tr!(lang, CloneSkipped, MAX_SET_STICKERS, skipped));
+        }
+        text.push_str(&tr!(lang, CloneNoKeywords));
+        text
+    } else {
+        let reason = last_error
+            .map(|e| error_message(&e, lang))
+            .unwrap_or_else(|| tr!(lang, CloneEmpty));
+        tr!(lang, CloneFailed, reason)
+    };
+    bot.edit_message_text(progress.chat.id, progress.id, text)
+        .send_retry()
+        .await?;
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn pack_command_handler(
+    bot: Bot,
+    msg: Message,
+    cmd: PackCommand,
+    me: Me,
+    packs: SharedPacks,
+    auth: SharedAuth,
+    limiter: SharedLimiter,
+    encoder: EncoderConfig,
+    lang: Lang,
+) -> Result<()> {
+    match cmd {
+        PackCommand::NewPack(args) => {
+            new_pack(
+                &bot, &msg, &args, &me, &packs, &auth, &limiter, &encoder, lang,
+            )
+            .await?;
+        }
+        PackCommand::AddSticker(args) => {
+            add_sticker(
+                &bot, &msg, &args, &me, &packs, &auth, &limiter, &encoder, lang,
+            )
+            .await?;
+        }
+        PackCommand::AddGrid(args) => {
+            add_grid(
+                &bot, &msg, &args, &me, &packs, &auth, &limiter, &encoder, lang,
+            )
+            .await?;
+        }
+        PackCommand::RemoveSticker => remove_sticker(&bot, &msg, &packs, lang).await?,
+        PackCommand::MoveSticker(args) => move_sticker(&bot, &msg, &args, &packs, lang).await?,
+        PackCommand::SetPackIcon(args) => {
+            set_pack_icon(
+                &bot, &msg, &args, &me, &packs, &auth, &limiter, &encoder, lang,
+            )
+            .await?;
+        }
+        PackCommand::Packs => list_packs(&bot, &msg, &packs, lang).await?,
+        PackCommand::ClonePack(args) => {
+            clone_pack(
+                &bot, &msg, &args, &me, &packs, &auth, &limiter, &encoder, lang,
+            )
+            .await?;
+        }
+        PackCommand::ExportPack(args) => {
+            export_pack(&bot, &msg, &args, &auth, &limiter, &encoder, lang).await?;
+        }
+    }
+    Ok(())
+}
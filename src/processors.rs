@@ -3,47 +3,292 @@ use std::path::Path;
 use std::process::{Command, Stdio};
 
 use anyhow::{Context, Result, anyhow};
-use image::imageops::FilterType;
-use image::{GenericImageView, ImageReader};
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, GenericImageView, RgbaImage};
+use tempfile::NamedTempFile;
 
-pub async fn process_image(input_path: &Path, output_path: &Path) -> Result<()> {
+use crate::background::remove_background;
+use crate::decode::load_image;
+use crate::effects::apply_effects;
+use crate::encode::{EncoderConfig, TooLarge, save_png, save_static};
+use crate::media::{Failure, output_tempfile};
+use crate::options::{Grid, ProcessOptions, TextOverlay, Trim};
+use crate::text::render_text_layer;
+use crate::transform::{square_filter, square_image, transform_filters, transform_image};
+use crate::trim::{trim_image, video_content_bounds};
+
+/// 自定义表情的边长
+pub const EMOJI_SIZE: u32 = 100;
+/// GIF 的大小上限（Telegram Bot API 限制 20MB）
+const MAX_GIF_SIZE: u64 = 20 * 1024 * 1024;
+/// 自定义表情的大小上限
+pub const EMOJI_MAX_BYTES: u64 = 64 * 1024;
+/// 静态贴纸包图标的大小上限（Telegram Bot API 限制 128KB）
+pub const STATIC_THUMBNAIL_MAX_BYTES: u64 = 128 * 1024;
+/// 视频贴纸包图标的大小上限（Telegram Bot API 限制 32KB）
+pub const VIDEO_THUMBNAIL_MAX_BYTES: u64 = 32 * 1024;
+
+/// 计算等比缩放后的尺寸，确保长边为 `max_side` 像素
+fn fit_dimensions(width: u32, height: u32, max_side: u32) -> (u32, u32) {
+    if width >= height {
+        let ratio = max_side as f32 / width as f32;
+        (max_side, ((height as f32 * ratio).round() as u32).max(1))
+    } else {
+        let ratio = max_side as f32 / height as f32;
+        (((width as f32 * ratio).round() as u32).max(1), max_side)
+    }
+}
+
+/// 缩放前的处理阶段：按选项执行几何变换、去除背景、裁剪边缘、添加描边和投影，最后补成正方形，
+/// `max_side` 为输出的边长
+fn prepare_image(mut img: DynamicImage, options: &ProcessOptions, max_side: u32) -> DynamicImage {
+    if !options.transform.is_identity() {
+        img = transform_image(img, &options.transform);
+    }
+    if let Some(removal) = &options.background {
+        let mut rgba = img.to_rgba8();
+        if remove_background(&mut rgba, removal) {
+            img = DynamicImage::ImageRgba8(rgba);
+        }
+    }
+    if let Some(trim) = &options.trim {
+        img = trim_image(img, output_padding(trim, max_side), max_side);
+    }
+    img = apply_effects(
+        img,
+        options.outline.as_ref(),
+        options.shadow.as_ref(),
+        max_side,
+    );
+    match &options.transform.square {
+        Some(fill) => square_image(img, fill),
+        None => img,
+    }
+}
+
+/// 在缩放后的图片上叠加文字
+fn draw_text(img: DynamicImage, overlay: &TextOverlay) -> Result<DynamicImage> {
+    let mut rgba = img.to_rgba8();
+    let layer = render_text_layer(overlay, rgba.width(), rgba.height())?;
+    imageops::overlay(&mut rgba, &layer, 0, 0);
+    Ok(DynamicImage::ImageRgba8(rgba))
+}
+
+/// 按输出尺寸换算留白（留白以 512 像素的贴纸为准）
+fn output_padding(trim: &Trim, max_side: u32) -> u32 {
+    trim.padding * max_side / 512
+}
+
+pub async fn process_image(
+    input_path: &Path,
+    output_path: &Path,
+    options: &ProcessOptions,
+    encoder: &EncoderConfig,
+) -> Result<()> {
     // 加载图片
-    let img = ImageReader::open(input_path)?
-        .with_guessed_format()?
-        .decode()?;
+    let img = load_image(input_path)?;
+    let img = prepare_image(img, options, 512);
 
     // 获取原始尺寸
     let (width, height) = img.dimensions();
 
     // 计算新尺寸，确保至少一边是512像素
-    let (new_width, new_height) = if width >= height {
-        let ratio = 512.0 / width as f32;
-        (512, (height as f32 * ratio).round() as u32)
-    } else {
-        let ratio = 512.0 / height as f32;
-        ((width as f32 * ratio).round() as u32, 512)
-    };
+    let (new_width, new_height) = fit_dimensions(width, height, 512);
 
     // 调整尺寸
+    let mut resized = img.resize_exact(new_width, new_height, FilterType::Lanczos3);
+    if let Some(overlay) = &options.text {
+        resized = draw_text(resized, overlay)?;
+    }
+
+    // 按编码配置保存为WebP或PNG格式
+    save_static(&resized, output_path, 512 * 1024, encoder)
+}
+
+/// 处理为自定义表情或贴纸包图标：100x100 的静态图片，非正方形图片居中并以透明像素填充
+pub async fn process_emoji_image(
+    input_path: &Path,
+    output_path: &Path,
+    options: &ProcessOptions,
+    encoder: &EncoderConfig,
+    max_bytes: u64,
+) -> Result<()> {
+    let img = load_image(input_path)?;
+    let img = prepare_image(img, options, EMOJI_SIZE);
+
+    let (width, height) = img.dimensions();
+    let (new_width, new_height) = fit_dimensions(width, height, EMOJI_SIZE);
     let resized = img.resize_exact(new_width, new_height, FilterType::Lanczos3);
 
-    // 保存为WebP格式，质量80%（可根据需要调整）
-    resized.save_with_format(output_path, image::ImageFormat::WebP)?;
+    let mut canvas = RgbaImage::new(EMOJI_SIZE, EMOJI_SIZE);
+    imageops::overlay(
+        &mut canvas,
+        &resized.to_rgba8(),
+        ((EMOJI_SIZE - new_width) / 2) as i64,
+        ((EMOJI_SIZE - new_height) / 2) as i64,
+    );
+    let mut emoji = DynamicImage::ImageRgba8(canvas);
+    if let Some(overlay) = &options.text {
+        emoji = draw_text(emoji, overlay)?;
+    }
 
-    // 检查文件大小
-    let file_size = fs::metadata(output_path)?.len();
-    if file_size > 512 * 1024 {
-        // 如果文件太大，尝试进一步压缩
-        return Err(anyhow!(
-            "图片太大 ({}KB)，即使压缩后仍超过512KB限制",
-            file_size / 1024
-        ));
+    save_static(&emoji, output_path, max_bytes, encoder)
+}
+
+/// 计算等比缩放后放进 `box_width`x`box_height` 的尺寸
+fn fit_within(width: u32, height: u32, box_width: u32, box_height: u32) -> (u32, u32) {
+    let ratio = (box_width as f32 / width as f32).min(box_height as f32 / height as f32);
+    (
+        ((width as f32 * ratio).round() as u32).clamp(1, box_width),
+        ((height as f32 * ratio).round() as u32).clamp(1, box_height),
+    )
+}
+
+/// 网格的总尺寸
+fn grid_size(grid: &Grid) -> (u32, u32) {
+    (grid.columns * EMOJI_SIZE, grid.rows * EMOJI_SIZE)
+}
+
+/// 网格中每一格的左上角坐标，逐行从左到右排列
+fn grid_cells(grid: &Grid) -> impl Iterator<Item = (u32, u32)> {
+    let columns = grid.columns;
+    (0..grid.rows).flat_map(move |row| {
+        (0..columns).map(move |column| (column * EMOJI_SIZE, row * EMOJI_SIZE))
+    })
+}
+
+/// 把透明图层居中放到网格大小的画布上
+fn center_on_grid(layer: &RgbaImage, grid: &Grid) -> RgbaImage {
+    let (width, height) = grid_size(grid);
+    let mut canvas = RgbaImage::new(width, height);
+    imageops::overlay(
+        &mut canvas,
+        layer,
+        ((width - layer.width()) / 2) as i64,
+        ((height - layer.height()) / 2) as i64,
+    );
+    canvas
+}
+
+/// 处理为自定义表情网格：整张图片等比缩放后居中放进 列x行 个 100x100 的格子，
+/// 逐行从左到右输出每一格的静态图片
+pub async fn process_emoji_grid_image(
+    input_path: &Path,
+    grid: &Grid,
+    options: &ProcessOptions,
+    encoder: &EncoderConfig,
+) -> Result<Vec<NamedTempFile>> {
+    let img = load_image(input_path)?;
+    let (grid_width, grid_height) = grid_size(grid);
+    let img = prepare_image(img, options, grid_width.max(grid_height));
+
+    let (width, height) = img.dimensions();
+    let (new_width, new_height) = fit_within(width, height, grid_width, grid_height);
+    let mut resized = img.resize_exact(new_width, new_height, FilterType::Lanczos3);
+    if let Some(overlay) = &options.text {
+        resized = draw_text(resized, overlay)?;
     }
+    let canvas = center_on_grid(&resized.to_rgba8(), grid);
 
-    Ok(())
+    let mut tiles = Vec::with_capacity(grid.count());
+    for (x, y) in grid_cells(grid) {
+        let tile = imageops::crop_imm(&canvas, x, y, EMOJI_SIZE, EMOJI_SIZE).to_image();
+        let output = output_tempfile(encoder.suffix())?;
+        save_static(
+            &DynamicImage::ImageRgba8(tile),
+            output.path(),
+            EMOJI_MAX_BYTES,
+            encoder,
+        )?;
+        tiles.push(output);
+    }
+    Ok(tiles)
+}
+
+/// 处理为自定义表情视频网格：与 [`process_emoji_grid_image`] 相同的切分方式，每一格单独编码
+///
+/// 视频只执行裁剪、旋转和翻转，不做边缘裁剪，叠加的文字同样按格切分后叠加到对应的格子上。
+pub async fn process_emoji_grid_webm(
+    input_path: &Path,
+    grid: &Grid,
+    options: &ProcessOptions,
+) -> Result<Vec<NamedTempFile>> {
+    let info = probe_video(input_path)?;
+    let (grid_width, grid_height) = grid_size(grid);
+    let (mut filters, (width, height)) =
+        transform_filters(&options.transform, info.width, info.height);
+    let (width, height) = fit_within(width, height, grid_width, grid_height);
+    filters.push(format!("scale={}:{}", width, height));
+    let scale = filters.join(",");
+    let text_layer = match &options.text {
+        Some(overlay) => Some(center_on_grid(
+            &render_text_layer(overlay, width, height)?,
+            grid,
+        )),
+        None => None,
+    };
+
+    let mut tiles = Vec::with_capacity(grid.count());
+    for (x, y) in grid_cells(grid) {
+        let filter = format!(
+            "{},format=yuva420p,pad={}:{}:(ow-iw)/2:(oh-ih)/2:color=0x00000000,crop={size}:{size}:{}:{}",
+            scale,
+            grid_width,
+            grid_height,
+            x,
+            y,
+            size = EMOJI_SIZE
+        );
+        let tile_layer = match &text_layer {
+            Some(layer) => {
+                let tile_layer = output_tempfile(".png")?;
+                imageops::crop_imm(layer, x, y, EMOJI_SIZE, EMOJI_SIZE)
+                    .to_image()
+                    .save_with_format(tile_layer.path(), image::ImageFormat::Png)?;
+                Some(tile_layer)
+            }
+            None => None,
+        };
+        let output = output_tempfile(".webm")?;
+        encode_vp9(
+            input_path,
+            output.path(),
+            &info,
+            &filter,
+            tile_layer.as_ref().map(|layer| layer.path()),
+            "120k",
+            EMOJI_MAX_BYTES,
+        )?;
+        tiles.push(output);
+    }
+    Ok(tiles)
+}
+
+/// 转换为 PNG，保留原始尺寸和透明通道
+pub async fn process_image_to_png(
+    input_path: &Path,
+    output_path: &Path,
+    encoder: &EncoderConfig,
+) -> Result<()> {
+    let img = load_image(input_path)?;
+    save_png(&img, output_path, encoder)
 }
 
-pub async fn process_webm(input_path: &Path, output_path: &Path) -> Result<()> {
+/// 视频信息
+#[derive(Clone, Copy, Debug)]
+pub struct VideoInfo {
+    pub width: u32,
+    pub height: u32,
+    pub fps: f32,
+    pub duration: f32,
+}
+
+/// 使用 ffprobe 获取视频信息
+pub fn probe_video(input_path: &Path) -> Result<VideoInfo> {
+    read_video_info(input_path).context(Failure::VideoProbe)
+}
+
+fn read_video_info(input_path: &Path) -> Result<VideoInfo> {
     // 使用ffprobe获取视频信息，改用JSON格式
     let mut command = Command::new("ffprobe");
     let output = command.args([
@@ -105,35 +350,171 @@ pub async fn process_webm(input_path: &Path, output_path: &Path) -> Result<()> {
         fps_str.parse().context("无法解析帧率")?
     };
 
+    Ok(VideoInfo {
+        width,
+        height,
+        fps,
+        duration,
+    })
+}
+
+/// 视频的缩放滤镜：按选项执行几何变换、裁剪到内容区域，缩放到长边为 `max_side`，
+/// 裁剪时四周加上透明留白，最后补成正方形
+///
+/// 同时返回滤镜输出的尺寸。
+fn video_scale_filter(
+    input_path: &Path,
+    info: &VideoInfo,
+    options: &ProcessOptions,
+    max_side: u32,
+) -> (String, (u32, u32)) {
+    let (mut filters, (mut width, mut height)) =
+        transform_filters(&options.transform, info.width, info.height);
+
+    let mut padding = 0;
+    if let Some(trim) = &options.trim {
+        match video_content_bounds(input_path, &filters.join(",")) {
+            Ok(Some((x, y, crop_width, crop_height))) => {
+                filters.push(format!("crop={}:{}:{}:{}", crop_width, crop_height, x, y));
+                (width, height) = (crop_width, crop_height);
+            }
+            Ok(None) => {}
+            Err(e) => log::warn!("检测视频内容区域失败，不裁剪: {:?}", e),
+        }
+        padding = output_padding(trim, max_side).min(max_side / 4);
+    }
+
+    // 内容缩放到长边为 max_side - 2 * padding，再四周各加 padding 像素的透明留白
+    let (new_width, new_height) = fit_dimensions(width, height, max_side - 2 * padding);
+    filters.push(format!("scale={}:{}", new_width, new_height));
+    let mut dimensions = (new_width + 2 * padding, new_height + 2 * padding);
+    if padding > 0 {
+        filters.push(format!(
+            "format=yuva420p,pad={}:{}:{pad}:{pad}:color=0x00000000",
+            dimensions.0,
+            dimensions.1,
+            pad = padding
+        ));
+    }
+    if let Some(fill) = &options.transform.square
+        && let Some(filter) = square_filter(fill, dimensions.0, dimensions.1)
+    {
+        filters.push(filter);
+        let side = dimensions.0.max(dimensions.1);
+        dimensions = (side, side);
+    }
+    (filters.join(","), dimensions)
+}
+
+pub async fn process_webm(
+    input_path: &Path,
+    output_path: &Path,
+    options: &ProcessOptions,
+) -> Result<()> {
+    let info = probe_video(input_path)?;
+
     // 计算新尺寸，确保至少一边是512像素
-    let (new_width, new_height) = if width >= height {
-        let ratio = 512.0 / width as f32;
-        (512, (height as f32 * ratio).round() as u32)
-    } else {
-        let ratio = 512.0 / height as f32;
-        ((width as f32 * ratio).round() as u32, 512)
+    let (filter, (width, height)) = video_scale_filter(input_path, &info, options, 512);
+
+    // 文字渲染为与视频同尺寸的透明图层，作为第二个输入叠加到每一帧
+    let text_layer = match &options.text {
+        Some(overlay) => {
+            let layer = output_tempfile(".png")?;
+            render_text_layer(overlay, width, height)?
+                .save_with_format(layer.path(), image::ImageFormat::Png)?;
+            Some(layer)
+        }
+        None => None,
     };
+    encode_vp9(
+        input_path,
+        output_path,
+        &info,
+        &filter,
+        text_layer.as_ref().map(|layer| layer.path()),
+        "200k",
+        256 * 1024,
+    )
+}
+
+/// 处理为自定义表情视频：100x100，非正方形视频居中并以透明像素填充
+pub async fn process_emoji_webm(
+    input_path: &Path,
+    output_path: &Path,
+    options: &ProcessOptions,
+    max_bytes: u64,
+) -> Result<()> {
+    let info = probe_video(input_path)?;
+
+    let (filter, _) = video_scale_filter(input_path, &info, options, EMOJI_SIZE);
+    let text_layer = match &options.text {
+        Some(overlay) => {
+            let layer = output_tempfile(".png")?;
+            render_text_layer(overlay, EMOJI_SIZE, EMOJI_SIZE)?
+                .save_with_format(layer.path(), image::ImageFormat::Png)?;
+            Some(layer)
+        }
+        None => None,
+    };
+    encode_vp9(
+        input_path,
+        output_path,
+        &info,
+        &format!(
+            "{},format=yuva420p,pad={size}:{size}:(ow-iw)/2:(oh-ih)/2:color=0x00000000",
+            filter,
+            size = EMOJI_SIZE
+        ),
+        text_layer.as_ref().map(|layer| layer.path()),
+        "120k",
+        max_bytes,
+    )
+}
+
+/// 使用 FFmpeg 编码为 VP9 WebM，限制帧率（30fps）、时长（3秒）和文件大小
+///
+/// 指定 `overlay` 时，该图片叠加在滤镜处理后的每一帧上。
+fn encode_vp9(
+    input_path: &Path,
+    output_path: &Path,
+    info: &VideoInfo,
+    video_filter: &str,
+    overlay: Option<&Path>,
+    bitrate: &str,
+    max_bytes: u64,
+) -> Result<()> {
+    let (fps, duration) = (info.fps, info.duration);
 
     // 设置帧率限制和时长限制
     let target_fps = if fps > 30.0 { 30 } else { fps.round() as u32 };
     let target_duration = if duration > 3.0 { 3.0 } else { duration };
 
+    let mut command = Command::new("ffmpeg");
+    command.args(["-y", "-i", input_path.to_str().unwrap()]);
+    match overlay {
+        Some(overlay) => command.args([
+            "-i",
+            overlay.to_str().unwrap(),
+            "-filter_complex",
+            &format!(
+                "[0:v]{},format=yuva420p[base];[base][1:v]overlay=0:0:format=auto",
+                video_filter
+            ),
+        ]),
+        None => command.args(["-vf", video_filter]),
+    };
+
     // 使用FFmpeg处理视频
-    let status = Command::new("ffmpeg")
+    let status = command
         .args([
-            "-y",
-            "-i",
-            input_path.to_str().unwrap(),
             "-t",
             &target_duration.to_string(),
-            "-vf",
-            &format!("scale={}:{}", new_width, new_height),
             "-r",
             &target_fps.to_string(),
             "-c:v",
             "libvpx-vp9",
             "-b:v",
-            "200k",
+            bitrate,
             "-auto-alt-ref",
             "0",
             "-pix_fmt",
@@ -147,16 +528,17 @@ pub async fn process_webm(input_path: &Path, output_path: &Path) -> Result<()> {
         .status()?;
 
     if !status.success() {
-        return Err(anyhow!("FFmpeg命令执行失败"));
+        return Err(Failure::VideoEncode.into());
     }
 
     // 检查文件大小
     let file_size = fs::metadata(output_path)?.len();
-    if file_size > 256 * 1024 {
-        return Err(anyhow!(
-            "视频太大 ({}KB)，即使压缩后仍超过256KB限制",
-            file_size / 1024
-        ));
+    if file_size > max_bytes {
+        return Err(TooLarge::Video {
+            size: file_size,
+            limit: max_bytes,
+        }
+        .into());
     }
 
     Ok(())
@@ -183,17 +565,47 @@ pub async fn process_video_to_gif(input_path: &Path, output_path: &Path) -> Resu
         .status()?;
 
     if !status.success() {
-        return Err(anyhow!("FFmpeg GIF生成失败"));
+        return Err(Failure::GifEncode.into());
     }
 
-    // 检查文件大小（Telegram Bot API 限制 20MB）
+    check_gif_size(output_path)
+}
+
+/// 检查生成的 GIF 是否超出大小限制
+fn check_gif_size(output_path: &Path) -> Result<()> {
     let file_size = fs::metadata(output_path)?.len();
-    if file_size > 20 * 1024 * 1024 {
-        return Err(anyhow!(
-            "GIF文件太大 ({}MB)，超过20MB限制",
-            file_size / (1024 * 1024)
-        ));
+    if file_size > MAX_GIF_SIZE {
+        return Err(TooLarge::Gif {
+            size: file_size,
+            limit: MAX_GIF_SIZE,
+        }
+        .into());
     }
-
     Ok(())
 }
+
+/// 使用外部 Lottie 渲染工具把 TGS 动画贴纸转换为 GIF
+///
+/// 工具由环境变量 TGS_CONVERTER 指定，默认为 python-lottie 的 `lottie_convert.py`，
+/// 以 `<工具> <输入.tgs> <输出.gif>` 的形式调用，按扩展名识别格式。
+pub async fn process_tgs_to_gif(input_path: &Path, output_path: &Path) -> Result<()> {
+    let converter =
+        std::env::var("TGS_CONVERTER").unwrap_or_else(|_| "lottie_convert.py".to_string());
+    // 下载的临时文件没有扩展名，复制一份带 .tgs 后缀的文件交给工具识别
+    let input = output_tempfile(".tgs")?;
+    fs::copy(input_path, input.path())?;
+
+    let status = Command::new(&converter)
+        .arg(input.path())
+        .arg(output_path)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .with_context(|| format!("无法运行TGS转换工具 {}", converter))
+        .context(Failure::TgsRender)?;
+    if !status.success() {
+        return Err(Failure::TgsRender.into());
+    }
+
+    check_gif_size(output_path)
+}
@@ -0,0 +1,388 @@
+use anyhow::Result;
+use image::{ImageReader, Rgba, RgbaImage, imageops};
+use teloxide::prelude::*;
+use teloxide::types::{InputFile, MessageOrigin};
+
+use crate::album::SharedQuotes;
+use crate::auth::SharedAuth;
+use crate::encode::EncoderConfig;
+use crate::handlers::{limited_user, reject_if_limited};
+use crate::i18n::Lang;
+use crate::limits::SharedLimiter;
+use crate::media::{Failure, download_file, error_message, output_tempfile};
+use crate::processors::process_image;
+use crate::reply::ReplyToExt;
+use crate::retry::RetryAfterExt;
+use crate::text::{render_text_block, wrap_lines};
+use crate::tr;
+
+/// 一张语录贴纸最多包含的消息数
+const MAX_MESSAGES: usize = 6;
+/// 单条消息最多显示的字数，超出部分省略
+const MAX_TEXT_CHARS: usize = 300;
+const AVATAR_SIZE: u32 = 72;
+const FONT_SIZE: u32 = 30;
+const NAME_FONT_SIZE: u32 = 26;
+/// 气泡内文字的最大宽度
+const MAX_TEXT_WIDTH: u32 = 640;
+const BUBBLE_PADDING: u32 = 20;
+const BUBBLE_RADIUS: u32 = 24;
+/// 名字与正文之间的距离
+const NAME_GAP: u32 = 10;
+/// 头像与气泡之间的距离
+const AVATAR_GAP: u32 = 12;
+/// 相邻消息之间的距离
+const MESSAGE_GAP: u32 = 8;
+const BUBBLE_COLOR: [u8; 3] = [0x1f, 0x2c, 0x3a];
+const TEXT_COLOR: [u8; 3] = [0xff, 0xff, 0xff];
+/// 名字和默认头像的颜色，与 Telegram 的配色一致，按发送者 ID 选取
+const NAME_COLORS: [[u8; 3]; 7] = [
+    [0xfc, 0x5c, 0x51],
+    [0xfa, 0x79, 0x0f],
+    [0x89, 0x5d, 0xd5],
+    [0x0f, 0xb2, 0x97],
+    [0x0f, 0xc9, 0xd6],
+    [0x3c, 0xa5, 0xec],
+    [0xd5, 0x4f, 0xaf],
+];
+
+/// 原消息的发送者
+#[derive(Clone, Debug, PartialEq)]
+struct Sender {
+    /// 用于选择颜色，也用于判断相邻消息是否来自同一发送者
+    id: i64,
+    name: String,
+    /// 可以获取头像的用户
+    user: Option<UserId>,
+}
+
+impl Sender {
+    /// 转发消息的原发送者，没有转发来源时为消息的发送者
+    fn of(msg: &Message) -> Self {
+        let from_user = |user: &teloxide::types::User| Self {
+            id: user.id.0 as i64,
+            name: user.full_name(),
+            user: Some(user.id),
+        };
+        let from_chat = |chat: &teloxide::types::Chat, signature: &Option<String>| Self {
+            id: chat.id.0,
+            name: signature
+                .clone()
+                .or_else(|| chat.title().map(str::to_string))
+                .unwrap_or_default(),
+            user: None,
+        };
+        match msg.forward_origin() {
+            Some(MessageOrigin::User { sender_user, .. }) => from_user(sender_user),
+            Some(MessageOrigin::HiddenUser {
+                sender_user_name, ..
+            }) => Self {
+                // 隐藏了账号的用户没有 ID，按名字区分
+                id: sender_user_name
+                    .bytes()
+                    .fold(0i64, |hash, byte| hash.wrapping_mul(31) + byte as i64),
+                name: sender_user_name.clone(),
+                user: None,
+            },
+            Some(MessageOrigin::Chat {
+                sender_chat,
+                author_signature,
+                ..
+            }) => from_chat(sender_chat, author_signature),
+            Some(MessageOrigin::Channel {
+                chat,
+                author_signature,
+                ..
+            }) => from_chat(chat, author_signature),
+            None => msg.from.as_ref().map(from_user).unwrap_or(Self {
+                id: msg.chat.id.0,
+                name: String::new(),
+                user: None,
+            }),
+        }
+    }
+
+    fn color(&self) -> [u8; 3] {
+        NAME_COLORS[self.id.unsigned_abs() as usize % NAME_COLORS.len()]
+    }
+}
+
+/// 像素 (x, y) 在圆角矩形内的覆盖率，边缘抗锯齿
+fn rounded_rect_coverage(x: u32, y: u32, width: u32, height: u32, radius: u32) -> f32 {
+    let radius = radius.min(width / 2).min(height / 2) as f32;
+    let (px, py) = (x as f32 + 0.5, y as f32 + 0.5);
+    // 到最近的圆角圆心的距离，不在圆角区域时视为完全覆盖
+    let cx = px.clamp(radius, width as f32 - radius);
+    let cy = py.clamp(radius, height as f32 - radius);
+    let distance = ((px - cx).powi(2) + (py - cy).powi(2)).sqrt();
+    (radius - distance + 0.5).clamp(0.0, 1.0)
+}
+
+/// 绘制纯色的圆角矩形
+fn rounded_rect(width: u32, height: u32, radius: u32, color: [u8; 3]) -> RgbaImage {
+    RgbaImage::from_fn(width, height, |x, y| {
+        let coverage = rounded_rect_coverage(x, y, width, height, radius);
+        Rgba([
+            color[0],
+            color[1],
+            color[2],
+            (coverage * 255.0).round() as u8,
+        ])
+    })
+}
+
+/// 把正方形图片裁成圆形
+fn mask_circle(img: &mut RgbaImage) {
+    let size = img.width();
+    for (x, y, pixel) in img.enumerate_pixels_mut() {
+        let coverage = rounded_rect_coverage(x, y, size, size, size / 2);
+        pixel[3] = (pixel[3] as f32 * coverage).round() as u8;
+    }
+}
+
+/// 下载用户的第一张头像，没有头像或不可见时返回 None
+async fn fetch_avatar(bot: &Bot, user: UserId) -> Result<Option<RgbaImage>> {
+    let photos = bot
+        .get_user_profile_photos(user)
+        .limit(1)
+        .send_retry()
+        .await?;
+    let Some(sizes) = photos.photos.first() else {
+        return Ok(None);
+    };
+    // 选择不小于头像尺寸的最小版本
+    let Some(photo) = sizes
+        .iter()
+        .find(|size| size.width.min(size.height) >= AVATAR_SIZE)
+        .or(sizes.last())
+    else {
+        return Ok(None);
+    };
+    let (file, _) = download_file(bot, photo.file.id.clone()).await?;
+    let img = ImageReader::open(file.path())?
+        .with_guessed_format()?
+        .decode()?;
+    Ok(Some(
+        img.resize_to_fill(AVATAR_SIZE, AVATAR_SIZE, imageops::FilterType::Lanczos3)
+            .to_rgba8(),
+    ))
+}
+
+/// 发送者的圆形头像，获取不到时使用带名字首字的彩色圆形
+async fn avatar(bot: &Bot, sender: &Sender) -> Result<RgbaImage> {
+    let photo = match sender.user {
+        Some(user) => fetch_avatar(bot, user).await.unwrap_or_else(|e| {
+            log::warn!("获取用户 {} 的头像失败: {:?}", user, e);
+            None
+        }),
+        None => None,
+    };
+    let mut img = match photo {
+        Some(photo) => photo,
+        None => {
+            let mut img = RgbaImage::from_pixel(AVATAR_SIZE, AVATAR_SIZE, {
+                let [r, g, b] = sender.color();
+                Rgba([r, g, b, 255])
+            });
+            if let Some(initial) = sender.name.chars().find(|c| !c.is_whitespace()) {
+                let letter =
+                    render_text_block(&[initial.to_uppercase().collect()], FONT_SIZE, TEXT_COLOR)?;
+                imageops::overlay(
+                    &mut img,
+                    &letter,
+                    (AVATAR_SIZE as i64 - letter.width() as i64) / 2,
+                    (AVATAR_SIZE as i64 - letter.height() as i64) / 2,
+                );
+            }
+            img
+        }
+    };
+    mask_circle(&mut img);
+    Ok(img)
+}
+
+/// 截断过长的消息
+fn truncate_text(text: &str) -> String {
+    if text.chars().count() <= MAX_TEXT_CHARS {
+        return text.to_string();
+    }
+    let mut truncated: String = text.chars().take(MAX_TEXT_CHARS).collect();
+    truncated.push('…');
+    truncated
+}
+
+/// 单条消息的气泡，同一发送者的连续消息只在第一条显示名字
+fn render_bubble(text: &str, name: Option<(&str, [u8; 3])>) -> Result<RgbaImage> {
+    let lines = wrap_lines(
+        &truncate_text(text),
+        MAX_TEXT_WIDTH as f32 / FONT_SIZE as f32,
+    );
+    let body = render_text_block(&lines, FONT_SIZE, TEXT_COLOR)?;
+    // 名字只显示一行
+    let name_line = name.and_then(|(name, color)| {
+        let line = wrap_lines(name, MAX_TEXT_WIDTH as f32 / NAME_FONT_SIZE as f32)
+            .into_iter()
+            .next()?;
+        Some((line, color))
+    });
+    let name = match name_line {
+        Some((line, color)) => Some(render_text_block(&[line], NAME_FONT_SIZE, color)?),
+        None => None,
+    };
+
+    let name_height = name.as_ref().map_or(0, |name| name.height() + NAME_GAP);
+    let content_width = body
+        .width()
+        .max(name.as_ref().map_or(0, |name| name.width()));
+    let mut bubble = rounded_rect(
+        content_width + 2 * BUBBLE_PADDING,
+        name_height + body.height() + 2 * BUBBLE_PADDING,
+        BUBBLE_RADIUS,
+        BUBBLE_COLOR,
+    );
+    let padding = BUBBLE_PADDING as i64;
+    if let Some(name) = &name {
+        imageops::overlay(&mut bubble, name, padding, padding);
+    }
+    imageops::overlay(&mut bubble, &body, padding, padding + name_height as i64);
+    Ok(bubble)
+}
+
+/// 合成语录图片：每条消息一个气泡，同一发送者的连续消息共用一个头像，头像与最后一个气泡底部对齐
+async fn render_quote(bot: &Bot, messages: &[Message]) -> Result<RgbaImage> {
+    let entries: Vec<(Sender, &str)> = messages
+        .iter()
+        .filter_map(|msg| Some((Sender::of(msg), msg.text()?)))
+        .take(MAX_MESSAGES)
+        .collect();
+    if entries.is_empty() {
+        return Err(Failure::QuoteNoText.into());
+    }
+
+    let mut rows = Vec::with_capacity(entries.len());
+    for (index, (sender, text)) in entries.iter().enumerate() {
+        let first_of_run = index == 0 || entries[index - 1].0 != *sender;
+        let last_of_run = entries
+            .get(index + 1)
+            .is_none_or(|(next, _)| next != sender);
+        let name = first_of_run.then(|| (sender.name.as_str(), sender.color()));
+        let bubble = render_bubble(text, name)?;
+        let avatar = if last_of_run {
+            Some(avatar(bot, sender).await?)
+        } else {
+            None
+        };
+        rows.push((bubble, avatar));
+    }
+
+    let bubble_x = AVATAR_SIZE + AVATAR_GAP;
+    let row_height = |(bubble, avatar): &(RgbaImage, Option<RgbaImage>)| {
+        bubble
+            .height()
+            .max(avatar.as_ref().map_or(0, |_| AVATAR_SIZE))
+    };
+    let width = bubble_x
+        + rows
+            .iter()
+            .map(|(bubble, _)| bubble.width())
+            .max()
+            .unwrap_or(0);
+    let height = rows.iter().map(row_height).sum::<u32>() + MESSAGE_GAP * (rows.len() as u32 - 1);
+
+    let mut canvas = RgbaImage::new(width, height);
+    let mut y = 0;
+    for row in &rows {
+        let (bubble, avatar) = row;
+        let bottom = y + row_height(row);
+        imageops::overlay(
+            &mut canvas,
+            bubble,
+            bubble_x as i64,
+            (bottom - bubble.height()) as i64,
+        );
+        if let Some(avatar) = avatar {
+            imageops::overlay(&mut canvas, avatar, 0, (bottom - AVATAR_SIZE) as i64);
+        }
+        y = bottom + MESSAGE_GAP;
+    }
+    Ok(canvas)
+}
+
+/// 生成语录贴纸并回复
+async fn send_quote(
+    bot: &Bot,
+    messages: Vec<Message>,
+    auth: &SharedAuth,
+    limiter: &SharedLimiter,
+    encoder: &EncoderConfig,
+    lang: Lang,
+) -> Result<()> {
+    let Some(first) = messages.first() else {
+        return Ok(());
+    };
+    let user_id = limited_user(first, auth);
+    if reject_if_limited(bot, first, user_id, 0, limiter, lang).await? {
+        return Ok(());
+    }
+    log::info!(
+        "ChatID: {}, 生成语录贴纸，共 {} 条消息",
+        first.chat.id,
+        messages.len()
+    );
+
+    let result = async {
+        let image = render_quote(bot, &messages).await?;
+        let input = output_tempfile(".png")?;
+        image.save_with_format(input.path(), image::ImageFormat::Png)?;
+        let output = output_tempfile(encoder.suffix())?;
+        process_image(input.path(), output.path(), &Default::default(), encoder).await?;
+        anyhow::Ok(output)
+    }
+    .await;
+    let output = match result {
+        Ok(output) => output,
+        Err(e) => {
+            log::error!("语录贴纸生成失败: {:?}", e);
+            bot.send_message(
+                first.chat.id,
+                tr!(lang, ProcessingFailed, error_message(&e, lang)),
+            )
+            .reply_to(first)
+            .send_retry()
+            .await?;
+            return Ok(());
+        }
+    };
+
+    bot.send_sticker(first.chat.id, InputFile::file(output.path()))
+        .emoji("💬")
+        .reply_to(first)
+        .send_retry()
+        .await?;
+    if let Some(user_id) = user_id {
+        limiter.record(user_id, 0).await?;
+    }
+    Ok(())
+}
+
+/// 转发来的文字消息：同一次转发的多条消息收集后合成一张语录贴纸
+pub async fn quote_handler(
+    bot: Bot,
+    msg: Message,
+    auth: SharedAuth,
+    limiter: SharedLimiter,
+    quotes: SharedQuotes,
+    encoder: EncoderConfig,
+    lang: Lang,
+) -> Result<()> {
+    let chat_id = msg.chat.id;
+    if quotes.push(chat_id, msg) {
+        tokio::spawn(async move {
+            let messages = quotes.collect(&chat_id).await;
+            if let Err(e) = send_quote(&bot, messages, &auth, &limiter, &encoder, lang).await {
+                log::error!("语录贴纸处理失败: {:?}", e);
+            }
+        });
+    }
+    Ok(())
+}
@@ -0,0 +1,77 @@
+use teloxide::payloads::{SendDocument, SendMediaGroup, SendMessage, SendSticker};
+use teloxide::requests::HasPayload;
+use teloxide::types::{Me, Message, MessageEntityKind, ReplyParameters};
+
+/// 支持回复引用与论坛话题的发送请求载荷
+pub trait ReplyPayload {
+    fn set_reply(&mut self, msg: &Message);
+}
+
+macro_rules! impl_reply_payload {
+    ($($payload:ty),* $(,)?) => {
+        $(
+            impl ReplyPayload for $payload {
+                fn set_reply(&mut self, msg: &Message) {
+                    self.reply_parameters =
+                        Some(ReplyParameters::new(msg.id).allow_sending_without_reply());
+                    // 论坛群组中需要指定话题，否则消息会发送到 General 话题
+                    if msg.is_topic_message {
+                        self.message_thread_id = msg.thread_id;
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_reply_payload!(SendMessage, SendSticker, SendDocument, SendMediaGroup);
+
+/// 为发送请求设置回复对象
+pub trait ReplyToExt: HasPayload + Sized
+where
+    Self::Payload: ReplyPayload,
+{
+    /// 以回复 `msg` 的形式发送，并保持在同一论坛话题中
+    fn reply_to(mut self, msg: &Message) -> Self {
+        self.payload_mut().set_reply(msg);
+        self
+    }
+}
+
+impl<R> ReplyToExt for R
+where
+    R: HasPayload,
+    R::Payload: ReplyPayload,
+{
+}
+
+/// 是否为群组或超级群组
+pub fn is_group_chat(msg: &Message) -> bool {
+    msg.chat.is_group() || msg.chat.is_supergroup()
+}
+
+/// 消息是否在呼叫机器人：说明文字或正文中提及了机器人、使用了 `/命令@机器人用户名`，或回复了机器人的消息
+///
+/// 按消息实体判断提及，`@机器人用户名_other` 之类的其他用户名不算，不带用户名的命令也不算。
+pub fn is_addressed_to_bot(msg: &Message, me: &Me) -> bool {
+    let mention = format!("@{}", me.username());
+    let mentioned = msg
+        .parse_caption_entities()
+        .or_else(|| msg.parse_entities())
+        .unwrap_or_default()
+        .iter()
+        .any(|entity| match entity.kind() {
+            MessageEntityKind::Mention => entity.text().eq_ignore_ascii_case(&mention),
+            MessageEntityKind::TextMention { user } => user.id == me.id,
+            MessageEntityKind::BotCommand => entity
+                .text()
+                .rsplit_once('@')
+                .is_some_and(|(_, username)| username.eq_ignore_ascii_case(me.username())),
+            _ => false,
+        });
+    let replied_to_bot = msg
+        .reply_to_message()
+        .and_then(|reply| reply.from.as_ref())
+        .is_some_and(|user| user.id == me.id);
+    mentioned || replied_to_bot
+}
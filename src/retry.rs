@@ -0,0 +1,36 @@
+use std::future::Future;
+
+use teloxide::RequestError;
+use teloxide::requests::{Output, Request};
+
+/// 遇到 RetryAfter（Telegram 洪水控制）时的最大重试次数
+const MAX_RETRIES: u32 = 3;
+
+/// 为 Telegram 请求提供 RetryAfter 自动重试
+pub trait RetryAfterExt: Request<Err = RequestError> + Send + Sync + Sized {
+    /// 发送请求；若 Telegram 返回 RetryAfter，则等待指定时间后重试
+    fn send_retry(self) -> impl Future<Output = Result<Output<Self>, RequestError>> + Send
+    where
+        Output<Self>: Send,
+    {
+        async move {
+            let mut attempts = 0;
+            loop {
+                match self.send_ref().await {
+                    Err(RequestError::RetryAfter(after)) if attempts < MAX_RETRIES => {
+                        attempts += 1;
+                        log::warn!(
+                            "触发 Telegram 洪水控制，{} 秒后重试 (第 {} 次)",
+                            after.seconds(),
+                            attempts
+                        );
+                        tokio::time::sleep(after.duration()).await;
+                    }
+                    result => return result,
+                }
+            }
+        }
+    }
+}
+
+impl<R> RetryAfterExt for R where R: Request<Err = RequestError> + Send + Sync {}
@@ -1,6 +1,14 @@
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
-use teloxide::types::ChatId;
+
+use anyhow::Result;
+use teloxide::types::{ChatId, UserId};
+
+use crate::i18n::{Lang, Msg};
+use crate::options::ProcessOptions;
+use crate::storage::{JsonWriter, load_json, to_json};
+
+const LANGS_FILE: &str = "langs.json";
 
 /// 工作模式枚举
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -11,31 +19,108 @@ pub enum Mode {
     GifDownload,
 }
 
-impl std::fmt::Display for Mode {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl Mode {
+    /// 模式名称
+    pub fn label(self, lang: Lang) -> &'static str {
         match self {
-            Mode::StickerOptimize => write!(f, "贴纸优化模式"),
-            Mode::GifDownload => write!(f, "GIF下载模式"),
+            Mode::StickerOptimize => Msg::ModeStickerOptimize.text(lang),
+            Mode::GifDownload => Msg::ModeGifDownload.text(lang),
+        }
+    }
+}
+
+/// 聊天的设置：工作模式和默认处理选项
+#[derive(Clone, Debug)]
+pub struct ChatSettings {
+    pub mode: Mode,
+    /// 该聊天中默认使用的处理选项，说明文字中的选项会覆盖它们
+    pub options: ProcessOptions,
+}
+
+impl Default for ChatSettings {
+    fn default() -> Self {
+        Self {
+            mode: Mode::StickerOptimize,
+            options: ProcessOptions::default(),
         }
     }
 }
 
-/// 聊天模式状态管理：每个 ChatId 对应一个 Mode
-pub type ModeState = Arc<Mutex<HashMap<ChatId, Mode>>>;
+/// 聊天状态管理：每个 ChatId 对应一份设置
+pub type ModeState = Arc<Mutex<HashMap<ChatId, ChatSettings>>>;
 
 /// 获取或初始化聊天的模式
 pub fn get_chat_mode(mode_state: &ModeState, chat_id: ChatId) -> Mode {
-    let mut modes = mode_state.lock().unwrap();
-    *modes.entry(chat_id).or_insert(Mode::StickerOptimize)
+    let mut chats = mode_state.lock().unwrap();
+    chats.entry(chat_id).or_default().mode
 }
 
 /// 切换聊天模式
 pub fn toggle_chat_mode(mode_state: &ModeState, chat_id: ChatId) -> Mode {
-    let mut modes = mode_state.lock().unwrap();
-    let current = modes.entry(chat_id).or_insert(Mode::StickerOptimize);
-    *current = match *current {
+    let mut chats = mode_state.lock().unwrap();
+    let settings = chats.entry(chat_id).or_default();
+    settings.mode = match settings.mode {
         Mode::StickerOptimize => Mode::GifDownload,
         Mode::GifDownload => Mode::StickerOptimize,
     };
-    *current
+    settings.mode
+}
+
+/// 聊天的默认处理选项
+pub fn get_chat_options(mode_state: &ModeState, chat_id: ChatId) -> ProcessOptions {
+    let chats = mode_state.lock().unwrap();
+    chats
+        .get(&chat_id)
+        .map(|settings| settings.options.clone())
+        .unwrap_or_default()
+}
+
+/// 修改聊天的默认处理选项，返回修改后的选项
+pub fn update_chat_options(
+    mode_state: &ModeState,
+    chat_id: ChatId,
+    update: impl FnOnce(&mut ProcessOptions),
+) -> ProcessOptions {
+    let mut chats = mode_state.lock().unwrap();
+    let settings = chats.entry(chat_id).or_default();
+    update(&mut settings.options);
+    settings.options.clone()
+}
+
+/// 用户通过 /lang 设置的语言，持久化到数据目录
+pub struct LangStore {
+    langs: Mutex<HashMap<UserId, Lang>>,
+    writer: JsonWriter,
+}
+
+pub type SharedLangs = Arc<LangStore>;
+
+impl LangStore {
+    /// 从数据目录加载语言设置
+    pub fn load() -> Result<Self> {
+        let langs = load_json(LANGS_FILE)?.unwrap_or_default();
+        Ok(Self {
+            langs: Mutex::new(langs),
+            writer: JsonWriter::new(LANGS_FILE),
+        })
+    }
+
+    /// 获取用户设置的语言，未设置时返回 None
+    pub fn get(&self, user_id: UserId) -> Option<Lang> {
+        self.langs.lock().unwrap().get(&user_id).copied()
+    }
+
+    /// 设置用户的语言，None 表示跟随客户端语言
+    pub async fn set(&self, user_id: UserId, lang: Option<Lang>) -> Result<()> {
+        {
+            let mut langs = self.langs.lock().unwrap();
+            match lang {
+                Some(lang) => langs.insert(user_id, lang),
+                None => langs.remove(&user_id),
+            };
+        }
+        self.writer
+            .save(|| to_json(&*self.langs.lock().unwrap()))
+            .await
+    }
 }
@@ -0,0 +1,84 @@
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+/// 数据目录，可通过 DATA_DIR 环境变量指定，默认为 ./data
+pub fn data_dir() -> PathBuf {
+    std::env::var("DATA_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from("data"))
+}
+
+/// 从数据目录读取 JSON 文件，文件不存在时返回 None
+pub fn load_json<T: DeserializeOwned>(name: &str) -> Result<Option<T>> {
+    let path = data_dir().join(name);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let content = fs::read_to_string(&path).with_context(|| format!("无法读取 {:?}", path))?;
+    let value = serde_json::from_str(&content).with_context(|| format!("无法解析 {:?}", path))?;
+    Ok(Some(value))
+}
+
+/// 将内容写入数据目录下的文件（先写临时文件再重命名，避免写入中断导致文件损坏）
+fn write_file(name: &str, content: &str) -> Result<()> {
+    let dir = data_dir();
+    fs::create_dir_all(&dir).with_context(|| format!("无法创建数据目录 {:?}", dir))?;
+    let path = dir.join(name);
+    let tmp_path = dir.join(format!("{}.tmp", name));
+    fs::write(&tmp_path, content).with_context(|| format!("无法写入 {:?}", tmp_path))?;
+    fs::rename(&tmp_path, &path).with_context(|| format!("无法重命名为 {:?}", path))?;
+    Ok(())
+}
+
+/// 在异步处理中持久化频繁更新的 JSON 文件
+///
+/// 数据只在调用方的锁内序列化，写盘交给阻塞线程池，不占用异步运行时和数据锁。
+/// 写入按顺序排队，每次写入前重新取快照，旧数据不会覆盖新数据。
+pub struct JsonWriter {
+    name: &'static str,
+    queue: tokio::sync::Mutex<()>,
+}
+
+impl JsonWriter {
+    pub fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            queue: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    /// 轮到本次写入时调用 `snapshot` 序列化最新数据并写入文件
+    pub async fn save(&self, snapshot: impl FnOnce() -> Result<String>) -> Result<()> {
+        let _queue = self.queue.lock().await;
+        let content = snapshot()?;
+        let name = self.name;
+        tokio::task::spawn_blocking(move || write_file(name, &content)).await?
+    }
+
+    /// 轮到本次写入时调用 `prepare` 生成新数据及其 JSON，写入成功后才调用 `apply` 把新数据应用到内存
+    ///
+    /// `prepare` 返回 None 表示没有变化，不写入。返回是否写入了新数据。
+    pub async fn commit<T: Send + 'static>(
+        &self,
+        prepare: impl FnOnce() -> Result<Option<(T, String)>>,
+        apply: impl FnOnce(T),
+    ) -> Result<bool> {
+        let _queue = self.queue.lock().await;
+        let Some((value, content)) = prepare()? else {
+            return Ok(false);
+        };
+        let name = self.name;
+        tokio::task::spawn_blocking(move || write_file(name, &content)).await??;
+        apply(value);
+        Ok(true)
+    }
+}
+
+/// 序列化为写入文件的 JSON 文本
+pub fn to_json<T: Serialize>(value: &T) -> Result<String> {
+    Ok(serde_json::to_string_pretty(value)?)
+}
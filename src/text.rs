@@ -0,0 +1,418 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use ab_glyph::{Font, FontVec, GlyphImageFormat, PxScale, ScaleFont, point};
+use anyhow::{Context, Result, anyhow};
+use image::imageops::{self, FilterType};
+use image::{Pixel, Rgba, RgbaImage};
+
+use crate::media::Failure;
+use crate::options::{TextOverlay, TextPosition};
+use crate::trim::content_bounds;
+
+/// 默认字体，Docker 镜像中自带（Noto Sans CJK，覆盖中日韩文字）
+const DEFAULT_FONT: &str = "/usr/share/fonts/stickerize/NotoSansCJK-Regular.ttc";
+/// 默认表情字体，Docker 镜像中自带（Noto Color Emoji）
+const DEFAULT_EMOJI_FONT: &str = "/usr/share/fonts/stickerize/NotoColorEmoji.ttf";
+/// 文字最多折成几行
+const MAX_LINES: usize = 3;
+/// 行高与字号之比
+const LINE_HEIGHT: f32 = 1.2;
+/// 表情的宽度（以字号为单位），彩色表情字体的字宽略大于一个字
+const EMOJI_WIDTH: f32 = 1.25;
+/// 文字区域最多占贴纸宽度和高度的比例
+const MAX_WIDTH_RATIO: f32 = 0.9;
+const MAX_HEIGHT_RATIO: f32 = 0.4;
+/// 字号上限（相对贴纸的长边）
+const MAX_FONT_RATIO: f32 = 0.16;
+/// 文字与贴纸边缘的距离（相对贴纸的高度）
+const MARGIN_RATIO: f32 = 0.04;
+
+/// 字体文件路径：环境变量 STICKER_FONT，默认使用镜像自带的字体
+pub fn font_path() -> Result<PathBuf> {
+    let path = std::env::var("STICKER_FONT")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from(DEFAULT_FONT));
+    if !path.is_file() {
+        return Err(anyhow!(
+            "找不到字体文件 {}，请通过 STICKER_FONT 指定",
+            path.display()
+        ));
+    }
+    Ok(path)
+}
+
+/// 表情字体路径：环境变量 EMOJI_FONT，默认使用镜像自带的字体，文件不存在时返回 None
+fn emoji_font_path() -> Option<PathBuf> {
+    let path = std::env::var("EMOJI_FONT")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from(DEFAULT_EMOJI_FONT));
+    path.is_file().then_some(path)
+}
+
+fn load_font(path: &Path) -> Result<FontVec> {
+    FontVec::try_from_vec_and_index(fs::read(path)?, 0)
+        .map_err(|_| anyhow!("无法解析字体文件 {}", path.display()))
+}
+
+/// 是否为表情符号（杂项符号、装饰符号及补充平面中的表情区块）
+fn is_emoji(c: char) -> bool {
+    matches!(
+        c as u32,
+        0x2300..=0x23FF | 0x2600..=0x27BF | 0x2B00..=0x2BFF | 0x1F000..=0x1FAFF
+    )
+}
+
+/// 不占宽度的字符：表情序列中的零宽连接符和变体选择符
+fn is_zero_width(c: char) -> bool {
+    matches!(c, '\u{200d}' | '\u{fe0e}' | '\u{fe0f}')
+}
+
+/// 估计字符宽度（以字号为单位）：ASCII 约半个字宽，中日韩文字为一个字宽，表情略宽
+fn char_width(c: char) -> f32 {
+    if is_zero_width(c) {
+        0.0
+    } else if is_emoji(c) {
+        EMOJI_WIDTH
+    } else if c.is_ascii() {
+        0.6
+    } else {
+        1.0
+    }
+}
+
+fn text_width(text: &str) -> f32 {
+    text.chars().map(char_width).sum()
+}
+
+/// 折行的最小单位：连续的 ASCII 字符组成一个单词，其他字符各自成为一个单位
+fn split_units(text: &str) -> Vec<String> {
+    let mut units: Vec<String> = Vec::new();
+    let mut word = String::new();
+    for c in text.chars() {
+        if c.is_ascii() && !c.is_ascii_whitespace() {
+            word.push(c);
+            continue;
+        }
+        if !word.is_empty() {
+            units.push(std::mem::take(&mut word));
+        }
+        units.push(c.to_string());
+    }
+    if !word.is_empty() {
+        units.push(word);
+    }
+    units
+}
+
+/// 把文字折成宽度不超过 `line_width` 的若干行，行首行尾的空格去掉
+fn wrap(units: &[String], line_width: f32) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for unit in units {
+        if !line.is_empty() && text_width(&line) + text_width(unit) > line_width {
+            lines.push(line.trim().to_string());
+            line.clear();
+        }
+        line.push_str(unit);
+    }
+    lines.push(line.trim().to_string());
+    lines.retain(|line| !line.is_empty());
+    lines
+}
+
+/// 自动排版：尝试不同的行数，选出字号最大的折行方式，返回各行文字和字号
+fn layout(text: &str, width: u32, height: u32) -> (Vec<String>, u32) {
+    let units = split_units(text);
+    let total = text_width(text);
+    let max_font = width.max(height) as f32 * MAX_FONT_RATIO;
+    let mut best = (vec![text.to_string()], 0.0f32);
+    for lines in 1..=MAX_LINES {
+        let wrapped = wrap(&units, total / lines as f32);
+        if wrapped.len() > MAX_LINES {
+            continue;
+        }
+        let widest = wrapped
+            .iter()
+            .map(|line| text_width(line))
+            .fold(0.0, f32::max);
+        let size = (width as f32 * MAX_WIDTH_RATIO / widest.max(1.0))
+            .min(height as f32 * MAX_HEIGHT_RATIO / (wrapped.len() as f32 * LINE_HEIGHT))
+            .min(max_font);
+        if size > best.1 {
+            best = (wrapped, size);
+        }
+    }
+    (best.0, (best.1.round() as u32).max(8))
+}
+
+/// 把文字按段落折成宽度不超过 `max_width`（以字号为单位）的若干行
+pub fn wrap_lines(text: &str, max_width: f32) -> Vec<String> {
+    text.lines()
+        .flat_map(|paragraph| {
+            let lines = wrap(&split_units(paragraph), max_width);
+            if lines.is_empty() {
+                vec![String::new()]
+            } else {
+                lines
+            }
+        })
+        .collect()
+}
+
+/// 文字的样式
+struct TextStyle {
+    font_size: u32,
+    color: [u8; 3],
+    /// 黑色描边的宽度，0 表示不描边
+    border: u32,
+    /// 多行文字居中对齐，否则左对齐
+    centered: bool,
+}
+
+/// 绘制文字使用的字体：表情和主字体中没有的字符使用表情字体
+struct Fonts {
+    main: FontVec,
+    emoji: Option<FontVec>,
+}
+
+impl Fonts {
+    /// 加载主字体和表情字体，表情字体缺失或无法解析时只记录警告，表情按主字体绘制
+    fn load() -> Result<Self> {
+        let main = load_font(&font_path()?)?;
+        let emoji = emoji_font_path().and_then(|path| {
+            load_font(&path)
+                .inspect_err(|e| log::warn!("加载表情字体失败，表情按主字体绘制: {:?}", e))
+                .ok()
+        });
+        Ok(Self { main, emoji })
+    }
+
+    /// 已加载的字体，首次绘制时从磁盘加载，之后复用；加载失败时下次绘制重新尝试
+    fn get() -> Result<&'static Self> {
+        static FONTS: OnceLock<Fonts> = OnceLock::new();
+        if let Some(fonts) = FONTS.get() {
+            return Ok(fonts);
+        }
+        let fonts = Self::load().context(Failure::FontMissing)?;
+        Ok(FONTS.get_or_init(|| fonts))
+    }
+
+    /// 绘制字符 `c` 使用的字体
+    fn font_for(&self, c: char) -> &FontVec {
+        match &self.emoji {
+            Some(emoji)
+                if (is_emoji(c) || self.main.glyph_id(c).0 == 0) && emoji.glyph_id(c).0 != 0 =>
+            {
+                emoji
+            }
+            _ => &self.main,
+        }
+    }
+}
+
+/// 字号为每 em 的像素数，换算为 ab_glyph 以字体高度计的缩放
+fn em_scale(font: &FontVec, font_size: f32) -> PxScale {
+    let units_per_em = font.units_per_em().unwrap_or(1000.0);
+    PxScale::from(font_size * font.height_unscaled() / units_per_em)
+}
+
+/// 一行中每个字符的字体和横坐标，以及整行的宽度
+fn place_line<'a>(
+    fonts: &'a Fonts,
+    line: &str,
+    font_size: f32,
+) -> (Vec<(char, &'a FontVec, f32)>, f32) {
+    let mut glyphs = Vec::new();
+    let mut x = 0.0;
+    for c in line.chars().filter(|c| !is_zero_width(*c)) {
+        let font = fonts.font_for(c);
+        glyphs.push((c, font, x));
+        x += font
+            .as_scaled(em_scale(font, font_size))
+            .h_advance(font.glyph_id(c));
+    }
+    (glyphs, x)
+}
+
+/// 按位图表情字体中的图片绘制表情，字体中没有图片时返回 false
+fn draw_raster_glyph(
+    layer: &mut RgbaImage,
+    font: &FontVec,
+    c: char,
+    font_size: f32,
+    (x, baseline): (f32, f32),
+) -> Result<bool> {
+    let Some(glyph) = font.glyph_raster_image2(font.glyph_id(c), font_size.round() as u16) else {
+        return Ok(false);
+    };
+    if !matches!(glyph.format, GlyphImageFormat::Png) {
+        return Ok(false);
+    }
+    let image = image::load_from_memory(glyph.data)?.to_rgba8();
+    let scale = font_size / glyph.pixels_per_em.max(1) as f32;
+    let width = ((image.width() as f32 * scale).round() as u32).max(1);
+    let height = ((image.height() as f32 * scale).round() as u32).max(1);
+    let image = imageops::resize(&image, width, height, FilterType::Triangle);
+    // 图片的偏移以基线为原点、向上为正
+    let left = x + glyph.origin.x * scale;
+    let top = baseline - (glyph.origin.y + glyph.height as f32) * scale;
+    imageops::overlay(layer, &image, left.round() as i64, top.round() as i64);
+    Ok(true)
+}
+
+/// 把 `coverage` 中的笔画向外扩展 `radius` 像素，得到描边的覆盖率
+fn dilate(coverage: &[f32], width: usize, height: usize, radius: u32) -> Vec<f32> {
+    let radius = radius as i64;
+    let offsets: Vec<(i64, i64)> = (-radius..=radius)
+        .flat_map(|dy| (-radius..=radius).map(move |dx| (dx, dy)))
+        .filter(|(dx, dy)| dx * dx + dy * dy <= radius * radius)
+        .collect();
+    let mut dilated = vec![0.0f32; coverage.len()];
+    for y in 0..height as i64 {
+        for x in 0..width as i64 {
+            dilated[y as usize * width + x as usize] = offsets
+                .iter()
+                .filter_map(|(dx, dy)| {
+                    let (sx, sy) = (x + dx, y + dy);
+                    (sx >= 0 && sy >= 0 && sx < width as i64 && sy < height as i64)
+                        .then(|| coverage[sy as usize * width + sx as usize])
+                })
+                .fold(0.0, f32::max);
+        }
+    }
+    dilated
+}
+
+/// 在 `width`x`height` 的透明画布上绘制文字
+///
+/// `anchor` 为 None 时文字块从左上角开始，否则水平居中，并按位置和边距摆放在画布的顶部、中间或底部。
+/// 表情使用表情字体中的彩色图片，与文字一起描边。
+fn draw_text_layer(
+    lines: &[String],
+    style: &TextStyle,
+    (width, height): (u32, u32),
+    anchor: Option<(TextPosition, u32)>,
+) -> Result<RgbaImage> {
+    let fonts = Fonts::get()?;
+    let font_size = style.font_size as f32;
+    let line_height = font_size * LINE_HEIGHT;
+    let main = fonts.main.as_scaled(em_scale(&fonts.main, font_size));
+    // 字体的上下高度在行内居中
+    let baseline_offset = (line_height + main.ascent() + main.descent()) / 2.0;
+
+    let block_height = lines.len() as f32 * line_height;
+    let top = match anchor {
+        None => 0.0,
+        Some((TextPosition::Top, margin)) => margin as f32,
+        Some((TextPosition::Center, _)) => (height as f32 - block_height) / 2.0,
+        Some((TextPosition::Bottom, margin)) => height as f32 - block_height - margin as f32,
+    };
+
+    let (w, h) = (width as usize, height as usize);
+    let mut coverage = vec![0.0f32; w * h];
+    let mut emoji_layer = RgbaImage::new(width, height);
+    for (index, line) in lines.iter().enumerate() {
+        let (glyphs, line_width) = place_line(fonts, line, font_size);
+        let left = if style.centered {
+            (width as f32 - line_width) / 2.0
+        } else {
+            0.0
+        };
+        let baseline = top + index as f32 * line_height + baseline_offset;
+        for (c, font, x) in glyphs {
+            let x = left + x;
+            if fonts
+                .emoji
+                .as_ref()
+                .is_some_and(|emoji| std::ptr::eq(font, emoji))
+                && draw_raster_glyph(&mut emoji_layer, font, c, font_size, (x, baseline))?
+            {
+                continue;
+            }
+            let glyph = font
+                .glyph_id(c)
+                .with_scale_and_position(em_scale(font, font_size), point(x, baseline));
+            let Some(outlined) = font.outline_glyph(glyph) else {
+                continue;
+            };
+            let bounds = outlined.px_bounds();
+            outlined.draw(|gx, gy, value| {
+                let (px, py) = (
+                    bounds.min.x as i64 + gx as i64,
+                    bounds.min.y as i64 + gy as i64,
+                );
+                if px >= 0 && py >= 0 && (px as usize) < w && (py as usize) < h {
+                    let cell = &mut coverage[py as usize * w + px as usize];
+                    *cell = cell.max(value.min(1.0));
+                }
+            });
+        }
+    }
+
+    let border = (style.border > 0).then(|| {
+        // 表情的轮廓同样描边
+        let outline: Vec<f32> = coverage
+            .iter()
+            .zip(emoji_layer.pixels())
+            .map(|(value, pixel)| value.max(pixel[3] as f32 / 255.0))
+            .collect();
+        dilate(&outline, w, h, style.border)
+    });
+    let [r, g, b] = style.color;
+    let mut layer = RgbaImage::new(width, height);
+    for (index, pixel) in layer.pixels_mut().enumerate() {
+        if let Some(border) = &border {
+            *pixel = Rgba([0, 0, 0, (border[index] * 255.0).round() as u8]);
+        }
+        pixel.blend(&Rgba([r, g, b, (coverage[index] * 255.0).round() as u8]));
+        pixel.blend(emoji_layer.get_pixel((index % w) as u32, (index / w) as u32));
+    }
+    Ok(layer)
+}
+
+/// 渲染 `width`x`height` 的透明文字图层：白色文字，黑色描边保证在任何背景上都清晰
+///
+/// 静态图片直接叠加该图层，视频则作为叠加帧输入。
+pub fn render_text_layer(overlay: &TextOverlay, width: u32, height: u32) -> Result<RgbaImage> {
+    let (lines, font_size) = layout(&overlay.text, width, height);
+    let border = (font_size / 12).max(2);
+    let margin = (height as f32 * MARGIN_RATIO).round() as u32 + border;
+    let style = TextStyle {
+        font_size,
+        color: [255, 255, 255],
+        border,
+        centered: true,
+    };
+    draw_text_layer(
+        &lines,
+        &style,
+        (width, height),
+        Some((overlay.position, margin)),
+    )
+}
+
+/// 渲染左对齐的文字块并裁剪到文字实际占据的区域，用于需要知道文字尺寸的排版
+///
+/// `lines` 为已经折好的各行。
+pub fn render_text_block(lines: &[String], font_size: u32, color: [u8; 3]) -> Result<RgbaImage> {
+    let widest = lines
+        .iter()
+        .map(|line| text_width(line))
+        .fold(0.0, f32::max);
+    // 画布按估计的尺寸留足余量，绘制后再裁剪
+    let width = ((widest + 2.0) * font_size as f32) as u32;
+    let height = ((lines.len() as f32 + 1.0) * font_size as f32 * LINE_HEIGHT * 1.5) as u32;
+    let style = TextStyle {
+        font_size,
+        color,
+        border: 0,
+        centered: false,
+    };
+    let layer = draw_text_layer(lines, &style, (width, height), None)?;
+    Ok(match content_bounds(&layer) {
+        Some((x, y, width, height)) => imageops::crop_imm(&layer, x, y, width, height).to_image(),
+        None => RgbaImage::new(1, 1),
+    })
+}
@@ -0,0 +1,212 @@
+use image::{DynamicImage, GenericImageView, Rgba, RgbaImage, imageops};
+
+use crate::options::{Crop, Flip, SquareFill, Transform};
+use crate::trim::Bounds;
+
+/// 裁剪区域在 `width`x`height` 的画面中的位置，超出画面的部分截掉，没有交集时返回 None
+fn crop_bounds(crop: &Crop, width: u32, height: u32) -> Option<Bounds> {
+    match *crop {
+        Crop::Rect {
+            x,
+            y,
+            width: crop_width,
+            height: crop_height,
+        } => {
+            if x >= width || y >= height {
+                return None;
+            }
+            Some((x, y, crop_width.min(width - x), crop_height.min(height - y)))
+        }
+        Crop::Aspect {
+            width: ratio_width,
+            height: ratio_height,
+        } => {
+            // 以宽或高为准，取能放下的最大区域
+            let (crop_width, crop_height) =
+                if width as u64 * ratio_height as u64 > height as u64 * ratio_width as u64 {
+                    (height * ratio_width / ratio_height, height)
+                } else {
+                    (width, width * ratio_height / ratio_width)
+                };
+            let (crop_width, crop_height) = (crop_width.max(1), crop_height.max(1));
+            Some((
+                (width - crop_width) / 2,
+                (height - crop_height) / 2,
+                crop_width,
+                crop_height,
+            ))
+        }
+    }
+}
+
+/// 是否为 90 度的整数倍
+fn right_angle(degrees: f32) -> Option<u32> {
+    [90, 180, 270]
+        .into_iter()
+        .find(|angle| (degrees - *angle as f32).abs() < 0.01)
+}
+
+/// 旋转后容纳整个画面所需的尺寸
+fn rotated_size(width: u32, height: u32, degrees: f32) -> (u32, u32) {
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    let (width, height) = (width as f32, height as f32);
+    (
+        (width * cos.abs() + height * sin.abs()).ceil().max(1.0) as u32,
+        (width * sin.abs() + height * cos.abs()).ceil().max(1.0) as u32,
+    )
+}
+
+/// 双线性采样，画面外视为透明，颜色按透明度加权避免边缘发黑
+fn sample_bilinear(img: &RgbaImage, x: f32, y: f32) -> Rgba<u8> {
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+    let mut color = [0f32; 3];
+    let mut alpha = 0f32;
+    for (dx, dy, weight) in [
+        (0, 0, (1.0 - fx) * (1.0 - fy)),
+        (1, 0, fx * (1.0 - fy)),
+        (0, 1, (1.0 - fx) * fy),
+        (1, 1, fx * fy),
+    ] {
+        let (px, py) = (x0 as i64 + dx, y0 as i64 + dy);
+        if px < 0 || py < 0 || px >= img.width() as i64 || py >= img.height() as i64 {
+            continue;
+        }
+        let pixel = img.get_pixel(px as u32, py as u32);
+        let weighted = weight * pixel[3] as f32;
+        for (channel, value) in color.iter_mut().zip(pixel.0) {
+            *channel += weighted * value as f32;
+        }
+        alpha += weighted;
+    }
+    if alpha <= 0.0 {
+        return Rgba([0, 0, 0, 0]);
+    }
+    let [r, g, b] = color.map(|channel| (channel / alpha).round() as u8);
+    Rgba([r, g, b, alpha.round().min(255.0) as u8])
+}
+
+/// 按任意角度顺时针旋转，画布扩大以容纳整个画面，空出的角为透明
+fn rotate_arbitrary(img: &RgbaImage, degrees: f32) -> RgbaImage {
+    let (width, height) = rotated_size(img.width(), img.height(), degrees);
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    let (source_cx, source_cy) = (img.width() as f32 / 2.0, img.height() as f32 / 2.0);
+    let (cx, cy) = (width as f32 / 2.0, height as f32 / 2.0);
+    RgbaImage::from_fn(width, height, |x, y| {
+        // 逆向映射回原图的坐标（像素中心）
+        let (dx, dy) = (x as f32 + 0.5 - cx, y as f32 + 0.5 - cy);
+        let source_x = dx * cos + dy * sin + source_cx - 0.5;
+        let source_y = -dx * sin + dy * cos + source_cy - 0.5;
+        sample_bilinear(img, source_x, source_y)
+    })
+}
+
+/// 对图片依次执行裁剪、旋转和翻转
+pub fn transform_image(mut img: DynamicImage, transform: &Transform) -> DynamicImage {
+    if let Some(crop) = &transform.crop {
+        let (width, height) = img.dimensions();
+        match crop_bounds(crop, width, height) {
+            Some((x, y, width, height)) => img = img.crop_imm(x, y, width, height),
+            None => log::warn!("裁剪区域在图片之外，不裁剪"),
+        }
+    }
+    if let Some(degrees) = transform.rotate {
+        img = match right_angle(degrees) {
+            Some(90) => img.rotate90(),
+            Some(180) => img.rotate180(),
+            Some(270) => img.rotate270(),
+            _ => DynamicImage::ImageRgba8(rotate_arbitrary(&img.to_rgba8(), degrees)),
+        };
+    }
+    match transform.flip {
+        Some(Flip::Horizontal) => img.fliph(),
+        Some(Flip::Vertical) => img.flipv(),
+        Some(Flip::Both) => img.fliph().flipv(),
+        None => img,
+    }
+}
+
+/// 以长边为边长补成正方形，原图居中
+pub fn square_image(img: DynamicImage, fill: &SquareFill) -> DynamicImage {
+    let (width, height) = img.dimensions();
+    let side = width.max(height);
+    let background = match fill {
+        SquareFill::Transparent => Rgba([0, 0, 0, 0]),
+        SquareFill::Color([r, g, b]) => Rgba([*r, *g, *b, 255]),
+    };
+    let mut canvas = RgbaImage::from_pixel(side, side, background);
+    imageops::overlay(
+        &mut canvas,
+        &img.to_rgba8(),
+        ((side - width) / 2) as i64,
+        ((side - height) / 2) as i64,
+    );
+    DynamicImage::ImageRgba8(canvas)
+}
+
+/// 与 [`transform_image`] 对应的 FFmpeg 滤镜（裁剪、旋转、翻转），同时返回变换后的画面尺寸
+pub fn transform_filters(
+    transform: &Transform,
+    width: u32,
+    height: u32,
+) -> (Vec<String>, (u32, u32)) {
+    let mut filters = Vec::new();
+    let (mut width, mut height) = (width, height);
+    if let Some(crop) = &transform.crop {
+        match crop_bounds(crop, width, height) {
+            Some((x, y, crop_width, crop_height)) => {
+                filters.push(format!("crop={}:{}:{}:{}", crop_width, crop_height, x, y));
+                (width, height) = (crop_width, crop_height);
+            }
+            None => log::warn!("裁剪区域在视频画面之外，不裁剪"),
+        }
+    }
+    if let Some(degrees) = transform.rotate {
+        match right_angle(degrees) {
+            Some(90) => filters.push("transpose=clock".to_string()),
+            Some(180) => filters.push("hflip,vflip".to_string()),
+            Some(270) => filters.push("transpose=cclock".to_string()),
+            _ => {
+                // 输出尺寸取偶数，便于后续以 yuva420p 编码
+                let (rotated_width, rotated_height) = rotated_size(width, height, degrees);
+                let (rotated_width, rotated_height) = (
+                    rotated_width.div_ceil(2) * 2,
+                    rotated_height.div_ceil(2) * 2,
+                );
+                filters.push(format!(
+                    "format=yuva420p,rotate={}:ow={}:oh={}:c=none",
+                    degrees.to_radians(),
+                    rotated_width,
+                    rotated_height
+                ));
+                (width, height) = (rotated_width, rotated_height);
+            }
+        }
+        if right_angle(degrees).is_some_and(|angle| angle != 180) {
+            (width, height) = (height, width);
+        }
+    }
+    match transform.flip {
+        Some(Flip::Horizontal) => filters.push("hflip".to_string()),
+        Some(Flip::Vertical) => filters.push("vflip".to_string()),
+        Some(Flip::Both) => filters.push("hflip,vflip".to_string()),
+        None => {}
+    }
+    (filters, (width, height))
+}
+
+/// 与 [`square_image`] 对应的 FFmpeg 滤镜，已是正方形时返回 None
+pub fn square_filter(fill: &SquareFill, width: u32, height: u32) -> Option<String> {
+    if width == height {
+        return None;
+    }
+    let color = match fill {
+        SquareFill::Transparent => "0x00000000".to_string(),
+        SquareFill::Color([r, g, b]) => format!("0x{:02x}{:02x}{:02x}ff", r, g, b),
+    };
+    Some(format!(
+        "format=yuva420p,pad={side}:{side}:(ow-iw)/2:(oh-ih)/2:color={}",
+        color,
+        side = width.max(height)
+    ))
+}
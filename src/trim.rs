@@ -0,0 +1,149 @@
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use anyhow::Result;
+use image::{DynamicImage, ImageReader, RgbaImage, imageops};
+
+use crate::background::{TRANSPARENT_ALPHA, color_distance, dominant_border_color};
+use crate::media::Failure;
+
+/// 判定为背景色的颜色容差（RGB 欧氏距离）
+const BORDER_TOLERANCE: f32 = 24.0;
+/// 边缘像素中至少有这么多比例是同一颜色时，才认为边缘是纯色背景
+const UNIFORM_BORDER_RATIO: f32 = 0.9;
+/// 检测视频内容区域时抽取的帧数
+const SAMPLE_FRAMES: u32 = 6;
+
+/// 内容区域：(x, y, 宽, 高)
+pub type Bounds = (u32, u32, u32, u32);
+
+/// 边缘是否几乎都是同一颜色，是则返回该颜色
+fn uniform_border_color(img: &RgbaImage) -> Option<[u8; 3]> {
+    let color = dominant_border_color(img)?;
+    let (width, height) = img.dimensions();
+    let border: Vec<_> = (0..width)
+        .flat_map(|x| [(x, 0), (x, height - 1)])
+        .chain((0..height).flat_map(|y| [(0, y), (width - 1, y)]))
+        .map(|(x, y)| img.get_pixel(x, y))
+        .collect();
+    let matching = border
+        .iter()
+        .filter(|pixel| {
+            pixel[3] < TRANSPARENT_ALPHA || color_distance(pixel, color) <= BORDER_TOLERANCE
+        })
+        .count();
+    (matching as f32 >= border.len() as f32 * UNIFORM_BORDER_RATIO).then_some(color)
+}
+
+/// 检测透明或纯色边缘以内的内容区域，整张图片都是背景时返回 None
+pub fn content_bounds(img: &RgbaImage) -> Option<Bounds> {
+    let (width, height) = img.dimensions();
+    if width == 0 || height == 0 {
+        return None;
+    }
+    let background = uniform_border_color(img);
+    let is_content = |x: u32, y: u32| {
+        let pixel = img.get_pixel(x, y);
+        pixel[3] >= TRANSPARENT_ALPHA
+            && background.is_none_or(|color| color_distance(pixel, color) > BORDER_TOLERANCE)
+    };
+
+    let (mut min_x, mut min_y, mut max_x, mut max_y) = (width, height, 0, 0);
+    for y in 0..height {
+        for x in 0..width {
+            if is_content(x, y) {
+                min_x = min_x.min(x);
+                min_y = min_y.min(y);
+                max_x = max_x.max(x);
+                max_y = max_y.max(y);
+            }
+        }
+    }
+    if min_x > max_x || min_y > max_y {
+        // 没有内容（整张图片都是背景）
+        return None;
+    }
+    Some((min_x, min_y, max_x - min_x + 1, max_y - min_y + 1))
+}
+
+/// 合并多个内容区域
+fn union_bounds(bounds: impl IntoIterator<Item = Bounds>) -> Option<Bounds> {
+    bounds
+        .into_iter()
+        .map(|(x, y, w, h)| (x, y, x + w, y + h))
+        .reduce(|a, b| (a.0.min(b.0), a.1.min(b.1), a.2.max(b.2), a.3.max(b.3)))
+        .map(|(x0, y0, x1, y1)| (x0, y0, x1 - x0, y1 - y0))
+}
+
+/// 将 `max_side` 像素画布中的留白换算为内容区域尺寸下的像素数
+///
+/// 内容缩放到 `max_side - 2 * padding` 后，四周的留白正好为 `padding` 像素。
+fn source_padding(content_long_side: u32, padding: u32, max_side: u32) -> u32 {
+    let padding = padding.min(max_side / 4);
+    let inner = max_side - 2 * padding;
+    (content_long_side as u64 * padding as u64).div_ceil(inner as u64) as u32
+}
+
+/// 裁剪到内容区域，并在四周加上透明留白（以 `max_side` 像素的输出为准）
+pub fn trim_image(img: DynamicImage, padding: u32, max_side: u32) -> DynamicImage {
+    let rgba = img.to_rgba8();
+    let Some((x, y, width, height)) = content_bounds(&rgba) else {
+        return img;
+    };
+    if padding == 0 && (width, height) == rgba.dimensions() {
+        return img;
+    }
+    let cropped = imageops::crop_imm(&rgba, x, y, width, height).to_image();
+    let pad = source_padding(width.max(height), padding, max_side);
+    if pad == 0 {
+        return DynamicImage::ImageRgba8(cropped);
+    }
+    let mut canvas = RgbaImage::new(width + 2 * pad, height + 2 * pad);
+    imageops::overlay(&mut canvas, &cropped, pad as i64, pad as i64);
+    DynamicImage::ImageRgba8(canvas)
+}
+
+/// 抽取视频的若干帧，检测所有帧内容区域的并集，空白帧不参与计算
+///
+/// `prefilter` 为抽帧前先执行的滤镜，坐标按其输出的画面计算。没有可裁剪的边缘时返回 None。
+pub fn video_content_bounds(input_path: &Path, prefilter: &str) -> Result<Option<Bounds>> {
+    let frames_dir = tempfile::tempdir()?;
+    let pattern = frames_dir.path().join("frame%02d.png");
+    let filter = if prefilter.is_empty() {
+        "fps=2".to_string()
+    } else {
+        format!("{},fps=2", prefilter)
+    };
+    let status = Command::new("ffmpeg")
+        .args([
+            "-y",
+            "-i",
+            input_path.to_str().unwrap(),
+            "-t",
+            "3",
+            "-vf",
+            &filter,
+            "-frames:v",
+            &SAMPLE_FRAMES.to_string(),
+            pattern.to_str().unwrap(),
+        ])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()?;
+    if !status.success() {
+        return Err(Failure::VideoDecode.into());
+    }
+
+    let mut bounds = Vec::new();
+    let mut dimensions = None;
+    for entry in std::fs::read_dir(frames_dir.path())? {
+        let frame = ImageReader::open(entry?.path())?
+            .with_guessed_format()?
+            .decode()?
+            .to_rgba8();
+        dimensions = Some(frame.dimensions());
+        bounds.extend(content_bounds(&frame));
+    }
+    Ok(union_bounds(bounds)
+        .filter(|&(x, y, width, height)| Some((width, height)) != dimensions || (x, y) != (0, 0)))
+}